@@ -0,0 +1,86 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use libipld_core::{
+    cid::Cid,
+    codec::{Codec, Decode, References},
+    ipld::Ipld,
+    raw_value::SkipOne,
+};
+use libipld_macro::ipld;
+use libipld_raw_cbor::{io::SliceReader, RawCborCodec};
+use multihash::{Code, MultihashDigest};
+use std::io::Cursor;
+
+/// A list of small records with integers, floats, strings, bytes and links.
+fn block() -> Vec<u8> {
+    let list = (0..1000u64)
+        .map(|i| {
+            let cid = Cid::new_v1(0x51, Code::Sha2_256.digest(&i.to_be_bytes()));
+            ipld!({
+                "id": i,
+                "score": i as f64 / 3.0,
+                "name": format!("record number {}", i),
+                "payload": vec![i as u8; 64],
+                "parent": cid,
+                "tags": ["a", "b", "c"],
+            })
+        })
+        .collect();
+    RawCborCodec.encode(&Ipld::List(list)).unwrap()
+}
+
+fn decode(c: &mut Criterion) {
+    let bytes = block();
+    let mut group = c.benchmark_group("decode");
+    group.bench_function("cursor", |b| {
+        b.iter(|| Ipld::decode(RawCborCodec, &mut Cursor::new(black_box(&bytes[..]))).unwrap())
+    });
+    group.bench_function("slice", |b| {
+        b.iter(|| Ipld::decode(RawCborCodec, &mut SliceReader::new(black_box(&bytes))).unwrap())
+    });
+    group.finish();
+}
+
+fn skip(c: &mut Criterion) {
+    let bytes = block();
+    let mut group = c.benchmark_group("skip");
+    group.bench_function("cursor", |b| {
+        b.iter(|| {
+            RawCborCodec
+                .skip(&mut Cursor::new(black_box(&bytes[..])))
+                .unwrap()
+        })
+    });
+    group.bench_function("slice", |b| {
+        b.iter(|| {
+            RawCborCodec
+                .skip(&mut SliceReader::new(black_box(&bytes)))
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn references(c: &mut Criterion) {
+    let bytes = block();
+    let mut group = c.benchmark_group("references");
+    group.bench_function("cursor", |b| {
+        b.iter(|| {
+            let mut set = Vec::new();
+            let mut r = Cursor::new(black_box(&bytes[..]));
+            <Ipld as References<RawCborCodec>>::references(RawCborCodec, &mut r, &mut set).unwrap();
+            set
+        })
+    });
+    group.bench_function("slice", |b| {
+        b.iter(|| {
+            let mut set = Vec::new();
+            let mut r = SliceReader::new(black_box(&bytes));
+            <Ipld as References<RawCborCodec>>::references(RawCborCodec, &mut r, &mut set).unwrap();
+            set
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, skip, references);
+criterion_main!(benches);
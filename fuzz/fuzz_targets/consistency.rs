@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../tests/consistency/check.rs"]
+mod check;
+
+fuzz_target!(|data: &[u8]| check::check(data));
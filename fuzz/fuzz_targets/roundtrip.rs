@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use libipld_core::{codec::Codec, ipld::Ipld};
+use libipld_raw_cbor::RawCborCodec;
+
+// Anything that decodes must encode to bytes that decode to the same encoding again. Comparing
+// encodings rather than values keeps NaN floats from failing the check.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(ipld) = RawCborCodec.decode::<Ipld>(data) {
+        let bytes = RawCborCodec.encode(&ipld).unwrap();
+        let ipld: Ipld = RawCborCodec.decode(&bytes).unwrap();
+        assert_eq!(bytes, RawCborCodec.encode(&ipld).unwrap());
+    }
+});
@@ -0,0 +1,128 @@
+//! Content addressed blocks.
+use crate::{RawCborCodec, CBOR};
+use core::convert::TryFrom;
+use libipld_core::{
+    cid::Cid,
+    codec::{Codec, Decode, Encode},
+    error::{InvalidMultihash, Result, UnsupportedMultihash},
+    ipld::Ipld,
+    multihash::MultihashDigest,
+};
+
+/// Computes the cid of cbor encoded `data` using the hash function `hcode`.
+pub fn cid<H: MultihashDigest<64>>(hcode: H, data: &[u8]) -> Cid {
+    Cid::new_v1(CBOR, hcode.digest(data))
+}
+
+/// A cbor encoded block together with its cid.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Block {
+    cid: Cid,
+    data: Vec<u8>,
+}
+
+impl Block {
+    /// Creates a block from a cid and data, verifying that the data matches the cid.
+    ///
+    /// The codec of the cid must be accepted by [`RawCborCodec`] and its hash code must be one of
+    /// the hash functions of `H`.
+    pub fn new<H: MultihashDigest<64>>(cid: Cid, data: Vec<u8>) -> Result<Self> {
+        RawCborCodec::try_from(cid.codec())?;
+        let code = cid.hash().code();
+        let hcode = H::try_from(code).map_err(|_| UnsupportedMultihash(code))?;
+        let hash = hcode.digest(&data);
+        if hash.digest() != cid.hash().digest() {
+            return Err(InvalidMultihash(hash.to_bytes()).into());
+        }
+        Ok(Self { cid, data })
+    }
+
+    /// Creates a block from a cid and data without verifying that they match.
+    pub fn new_unchecked(cid: Cid, data: Vec<u8>) -> Self {
+        Self { cid, data }
+    }
+
+    /// Encodes `value` and computes its cid using the hash function `hcode`.
+    pub fn encode<H, T>(hcode: H, value: &T) -> Result<Self>
+    where
+        H: MultihashDigest<64>,
+        T: Encode<RawCborCodec> + ?Sized,
+    {
+        let data = RawCborCodec.encode(value)?;
+        Ok(Self {
+            cid: cid(hcode, &data),
+            data,
+        })
+    }
+
+    /// The cid of the block.
+    pub fn cid(&self) -> &Cid {
+        &self.cid
+    }
+
+    /// The encoded data of the block.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the cid and the encoded data.
+    pub fn into_inner(self) -> (Cid, Vec<u8>) {
+        (self.cid, self.data)
+    }
+
+    /// Decodes the block.
+    pub fn decode<T: Decode<RawCborCodec>>(&self) -> Result<T> {
+        RawCborCodec.decode(&self.data)
+    }
+
+    /// Collects the links contained in the block.
+    pub fn references<E: Extend<Cid>>(&self, set: &mut E) -> Result<()> {
+        RawCborCodec.references::<Ipld, E>(&self.data, set)
+    }
+}
+
+impl AsRef<[u8]> for Block {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CborError;
+    use libipld_core::error::UnsupportedCodec;
+    use libipld_macro::ipld;
+    use multihash::Code;
+
+    #[test]
+    fn roundtrip() {
+        let link = Cid::new_v1(CBOR, Code::Sha2_256.digest(b"link"));
+        let ipld = ipld!({ "a": [1, 2], "link": link });
+        let block = Block::encode(Code::Blake3_256, &ipld).unwrap();
+        assert_eq!(block.cid().codec(), CBOR);
+        assert_eq!(block.cid().hash().code(), u64::from(Code::Blake3_256));
+        let (cid, data) = block.clone().into_inner();
+        let block2 = Block::new::<Code>(cid, data).unwrap();
+        assert_eq!(block, block2);
+        assert_eq!(block2.decode::<Ipld>().unwrap(), ipld);
+        let mut links = Vec::new();
+        block2.references(&mut links).unwrap();
+        assert_eq!(links, vec![link]);
+    }
+
+    #[test]
+    fn verify() {
+        let block = Block::encode(Code::Sha2_256, &(1u8, "x".to_string())).unwrap();
+        let (cid, mut data) = block.into_inner();
+        data[1] = 2;
+        Block::new::<Code>(cid, data.clone())
+            .unwrap_err()
+            .downcast::<InvalidMultihash>()
+            .unwrap();
+        let cid = Cid::new_v1(0x55, *cid.hash());
+        let err = Block::new::<Code>(cid, data).unwrap_err();
+        assert_eq!(CborError::from(&err), CborError::UnsupportedCodec(0x55));
+        err.downcast::<UnsupportedCodec>().unwrap();
+    }
+}
@@ -0,0 +1,1587 @@
+//! Validation against [CDDL](https://www.rfc-editor.org/rfc/rfc8610) schemas.
+//!
+//! [`Schema::parse`] reads a set of rules and [`Schema::validate`] checks an encoded item against
+//! one of them without decoding it. The supported subset covers what block formats usually need:
+//!
+//! - type choices `a / b`, ranges `0..10` and `0...10`, number, text and `h'...'` byte string
+//!   literals and the controls `.size`, `.lt`, `.le`, `.gt`, `.ge`, `.eq`, `.ne` and `.default`
+//! - arrays `[...]` and maps `{...}` with the occurrence indicators `?`, `*`, `+` and `n*m`, group
+//!   choices `//`, inline groups `(...)` and named groups
+//! - map keys written as `name:`, as literals such as `1:` and as types such as `uint =>`, so
+//!   maps with keys other than strings can be described. Keys written with `:` imply a cut
+//! - tags `#6.n(type)`, e.g. `#6.42(bstr)` for links, and `#` for any item
+//! - the types of the standard prelude, such as `uint`, `tstr`, `float32` or `bool`
+//!
+//! Generics, sockets, `/=` and `//=`, unwrapping with `~`, choices from groups with `&` and all
+//! other controls fail to parse with [`InvalidCddl`].
+use crate::{
+    error::{InvalidCddl, SchemaMismatch, ValidationTooDeep},
+    io::SliceReader,
+    path::PathSegment,
+    token::{read_token, Token},
+    view::{CborRef, Kind},
+};
+use libipld_core::error::{Error, Result};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+};
+
+/// The types of the standard prelude (RFC 8610, appendix D) that can be checked.
+const PRELUDE: &[&str] = &[
+    "any",
+    "uint",
+    "unsigned",
+    "nint",
+    "negative",
+    "int",
+    "integer",
+    "number",
+    "bstr",
+    "bytes",
+    "tstr",
+    "text",
+    "tdate",
+    "time",
+    "float16",
+    "float32",
+    "float64",
+    "float16-32",
+    "float32-64",
+    "float",
+    "false",
+    "true",
+    "bool",
+    "nil",
+    "null",
+    "undefined",
+];
+
+/// How deeply types may be nested in a schema, and how deeply rules may reference each other
+/// while validating, which bounds the recursion of recursive rules.
+const MAX_DEPTH: usize = 128;
+
+/// A type with all its choices, e.g. `int / tstr`.
+#[derive(Clone, Debug)]
+struct Type {
+    choices: Vec<Type1>,
+    /// The schema text of the type, for reporting mismatches.
+    text: String,
+}
+
+/// A single choice of a type, possibly restricted by a range or control operator.
+#[derive(Clone, Debug)]
+struct Type1 {
+    base: Type2,
+    op: Option<(Op, Type2)>,
+}
+
+/// A range or control operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    /// `..` if inclusive, `...` otherwise.
+    Range(bool),
+    Size,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Default,
+}
+
+#[derive(Clone, Debug)]
+enum Type2 {
+    Int(i128),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    /// A rule or a type of the prelude.
+    Name(String),
+    Paren(Type),
+    Array(Group),
+    Map(Group),
+    /// `#6.n(type)`, or `#6(type)` for any tag.
+    Tag(Option<u64>, Box<Type>),
+    /// `#`.
+    Any,
+}
+
+/// The choices of a group, each a sequence of entries.
+#[derive(Clone, Debug)]
+struct Group {
+    choices: Vec<Vec<Entry>>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    min: usize,
+    max: usize,
+    kind: EntryKind,
+    /// The schema text of the entry, for reporting missing map entries.
+    text: String,
+}
+
+#[derive(Clone, Debug)]
+enum EntryKind {
+    /// A type with an optional map key. Without a key it may also name a group.
+    Member(Option<Key>, Type),
+    /// An inline group `(...)`.
+    Group(Group),
+}
+
+#[derive(Clone, Debug)]
+enum Key {
+    /// `name:`, which matches the text key `name`.
+    Name(String),
+    /// A literal followed by `:`.
+    Literal(Type),
+    /// `type =>`.
+    Type(Type),
+}
+
+impl Key {
+    /// Whether the key implies a cut (RFC 8610, section 3.5.4), so that an entry with a matching
+    /// key but a value that doesn't match fails the map instead of being left to later entries.
+    fn is_cut(&self) -> bool {
+        !matches!(self, Self::Type(_))
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Rule {
+    Type(Type),
+    Group(Group),
+}
+
+/// Where and why parsing failed.
+#[derive(Clone, Copy, Debug)]
+struct ParseError {
+    pos: usize,
+    message: &'static str,
+}
+
+type Parsed<T> = core::result::Result<T, ParseError>;
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// The number of parentheses, brackets, braces and tags the current type is nested in.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            pos: self.pos,
+            message,
+        }
+    }
+
+    /// Runs `f` one level deeper, failing if that is more than [`MAX_DEPTH`] levels.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Parsed<T>) -> Parsed<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("types nested deeper than 128 levels"));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_ws(&mut self) {
+        let src = self.src;
+        loop {
+            let rest = &src[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if !trimmed.starts_with(';') {
+                break;
+            }
+            self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.src[self.pos..].starts_with(s)
+    }
+
+    /// Consumes `s` after skipping whitespace, if it comes next.
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_ws();
+        let found = self.starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, s: &str, message: &'static str) -> Parsed<()> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+
+    /// Reads an identifier, where dashes and dots may only occur between other characters.
+    fn name(&mut self) -> Option<String> {
+        fn is_ealpha(b: u8) -> bool {
+            b.is_ascii_alphabetic() || matches!(b, b'@' | b'_' | b'$')
+        }
+
+        let src = self.src;
+        let bytes = src.as_bytes();
+        let start = self.pos;
+        if !bytes.get(start).is_some_and(|b| is_ealpha(*b)) {
+            return None;
+        }
+        let mut end = start + 1;
+        loop {
+            let mut next = end;
+            while matches!(bytes.get(next), Some(b'-' | b'.')) {
+                next += 1;
+            }
+            match bytes.get(next) {
+                Some(b) if is_ealpha(*b) || b.is_ascii_digit() => end = next + 1,
+                _ => break,
+            }
+        }
+        self.pos = end;
+        Some(src[start..end].to_string())
+    }
+
+    /// Reads an unsigned integer without a sign or a prefix.
+    fn uint(&mut self) -> Option<u64> {
+        let src = self.src;
+        let digits = src[self.pos..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
+            .count();
+        let value = src[self.pos..self.pos + digits].parse().ok()?;
+        self.pos += digits;
+        Some(value)
+    }
+
+    /// Returns `true` if the next token is the end of the input or the start of the next rule.
+    fn at_rule_end(&mut self) -> bool {
+        self.skip_ws();
+        let start = self.pos;
+        let end = self.peek().is_none()
+            || (self.name().is_some() && {
+                self.skip_ws();
+                (self.starts_with("=") && !self.starts_with("=>"))
+                    || self.starts_with("/=")
+                    || self.starts_with("//=")
+            });
+        self.pos = start;
+        end
+    }
+
+    fn rules(&mut self) -> Parsed<Vec<(String, Rule)>> {
+        let mut rules = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.peek().is_none() {
+                return Ok(rules);
+            }
+            let name = self
+                .name()
+                .ok_or_else(|| self.error("expected a rule name"))?;
+            self.expect("=", "expected `=`")?;
+            rules.push((name, self.rule()?));
+        }
+    }
+
+    /// Reads the right hand side of a rule, which is a type if it can be read as one and a
+    /// group otherwise.
+    fn rule(&mut self) -> Parsed<Rule> {
+        let start = self.pos;
+        let type_err = match self.ty() {
+            Ok(ty) if self.at_rule_end() => return Ok(Rule::Type(ty)),
+            Ok(_) => self.error("expected the next rule"),
+            Err(err) => err,
+        };
+        self.pos = start;
+        match self.group() {
+            Ok(group) if self.at_rule_end() => Ok(Rule::Group(group)),
+            Ok(_) => Err(self.error("expected the next rule").further(type_err)),
+            Err(err) => Err(err.further(type_err)),
+        }
+    }
+
+    fn ty(&mut self) -> Parsed<Type> {
+        self.skip_ws();
+        let start = self.pos;
+        let mut choices = vec![self.type1()?];
+        let mut end = self.pos;
+        loop {
+            self.skip_ws();
+            if !self.starts_with("/") || self.starts_with("//") || self.starts_with("/=") {
+                break;
+            }
+            self.pos += 1;
+            choices.push(self.type1()?);
+            end = self.pos;
+        }
+        self.pos = end;
+        Ok(Type {
+            choices,
+            text: self.src[start..end].to_string(),
+        })
+    }
+
+    fn type1(&mut self) -> Parsed<Type1> {
+        let base = self.type2()?;
+        let end = self.pos;
+        let op = if self.eat("...") {
+            Op::Range(false)
+        } else if self.eat("..") {
+            Op::Range(true)
+        } else if let Some(op) = self.control()? {
+            op
+        } else {
+            self.pos = end;
+            return Ok(Type1 { base, op: None });
+        };
+        let arg = self.type2()?;
+        let valid = match op {
+            Op::Range(_) => matches!(
+                (literal(&base), literal(&arg)),
+                (Some(lower), Some(upper)) if same_kind(lower, upper)
+            ),
+            Op::Size => matches!(arg, Type2::Int(_) | Type2::Paren(_)),
+            Op::Default => true,
+            _ => literal(&arg).is_some(),
+        };
+        if !valid {
+            return Err(self.error("unsupported operand"));
+        }
+        Ok(Type1 {
+            base,
+            op: Some((op, arg)),
+        })
+    }
+
+    fn control(&mut self) -> Parsed<Option<Op>> {
+        self.skip_ws();
+        let start = self.pos;
+        if !self.starts_with(".") {
+            return Ok(None);
+        }
+        self.pos += 1;
+        let op = match self.name().as_deref() {
+            Some("size") => Op::Size,
+            Some("lt") => Op::Lt,
+            Some("le") => Op::Le,
+            Some("gt") => Op::Gt,
+            Some("ge") => Op::Ge,
+            Some("eq") => Op::Eq,
+            Some("ne") => Op::Ne,
+            Some("default") => Op::Default,
+            _ => {
+                return Err(ParseError {
+                    pos: start,
+                    message: "unsupported control operator",
+                })
+            }
+        };
+        Ok(Some(op))
+    }
+
+    fn type2(&mut self) -> Parsed<Type2> {
+        self.skip_ws();
+        match self.peek() {
+            Some(b'"') => Ok(Type2::Text(self.text()?)),
+            Some(b'h') if self.starts_with("h'") => Ok(Type2::Bytes(self.hex_bytes()?)),
+            Some(b'(') => self.nested(|p| {
+                p.pos += 1;
+                let ty = p.ty()?;
+                p.expect(")", "expected `)`")?;
+                Ok(Type2::Paren(ty))
+            }),
+            Some(b'[') => self.nested(|p| {
+                p.pos += 1;
+                let group = p.group()?;
+                p.expect("]", "expected `]`")?;
+                Ok(Type2::Array(group))
+            }),
+            Some(b'{') => self.nested(|p| {
+                p.pos += 1;
+                let group = p.group()?;
+                p.expect("}", "expected `}`")?;
+                Ok(Type2::Map(group))
+            }),
+            Some(b'#') => self.nested(Self::tag),
+            Some(b) if b.is_ascii_digit() || b == b'-' => self.number(),
+            _ => match self.name() {
+                Some(name) => Ok(Type2::Name(name)),
+                None => Err(self.error("expected a type")),
+            },
+        }
+    }
+
+    fn number(&mut self) -> Parsed<Type2> {
+        let src = self.src;
+        let bytes = src.as_bytes();
+        let start = self.pos;
+        let invalid = ParseError {
+            pos: start,
+            message: "invalid number",
+        };
+        let digit = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+        let mut end = start;
+        let negative = bytes[end] == b'-';
+        if negative {
+            end += 1;
+        }
+        if src[end..].starts_with("0x") {
+            let digits = &src[end + 2..];
+            let len = digits.bytes().take_while(u8::is_ascii_hexdigit).count();
+            let value = i128::from_str_radix(&digits[..len], 16).map_err(|_| invalid)?;
+            self.pos = end + 2 + len;
+            return Ok(Type2::Int(if negative { -value } else { value }));
+        }
+        if !digit(end) {
+            return Err(invalid);
+        }
+        while digit(end) {
+            end += 1;
+        }
+        let mut float = false;
+        if bytes.get(end) == Some(&b'.') && digit(end + 1) {
+            float = true;
+            end += 1;
+            while digit(end) {
+                end += 1;
+            }
+        }
+        if matches!(bytes.get(end), Some(b'e' | b'E')) {
+            let mut exponent = end + 1;
+            if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+                exponent += 1;
+            }
+            if digit(exponent) {
+                float = true;
+                end = exponent;
+                while digit(end) {
+                    end += 1;
+                }
+            }
+        }
+        self.pos = end;
+        let text = &src[start..end];
+        let value = if float {
+            text.parse().map(Type2::Float).ok()
+        } else {
+            text.parse().map(Type2::Int).ok()
+        };
+        value.ok_or(invalid)
+    }
+
+    /// Reads a text string literal, where a backslash escapes the next character.
+    fn text(&mut self) -> Parsed<String> {
+        let src = self.src;
+        let start = self.pos;
+        let unterminated = ParseError {
+            pos: start,
+            message: "unterminated text string",
+        };
+        let mut chars = src[start + 1..].char_indices();
+        let mut text = String::new();
+        loop {
+            match chars.next() {
+                Some((i, '"')) => {
+                    self.pos = start + 1 + i + 1;
+                    return Ok(text);
+                }
+                Some((_, '\\')) => text.push(chars.next().ok_or(unterminated)?.1),
+                Some((_, c)) => text.push(c),
+                None => return Err(unterminated),
+            }
+        }
+    }
+
+    /// Reads a byte string literal in hex, which may contain whitespace.
+    fn hex_bytes(&mut self) -> Parsed<Vec<u8>> {
+        fn hex_digit(b: u8) -> u8 {
+            (b as char).to_digit(16).unwrap_or(0) as u8
+        }
+
+        let src = self.src;
+        let start = self.pos + 2;
+        let end = src[start..]
+            .find('\'')
+            .map(|i| start + i)
+            .ok_or(ParseError {
+                pos: self.pos,
+                message: "unterminated byte string",
+            })?;
+        let digits = src[start..end]
+            .bytes()
+            .filter(|b| !b.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+        if digits.len() % 2 != 0 || !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(self.error("invalid byte string"));
+        }
+        self.pos = end + 1;
+        Ok(digits
+            .chunks(2)
+            .map(|pair| (hex_digit(pair[0]) << 4) | hex_digit(pair[1]))
+            .collect())
+    }
+
+    fn tag(&mut self) -> Parsed<Type2> {
+        let start = self.pos;
+        self.pos += 1;
+        match self.peek() {
+            Some(b'6') => self.pos += 1,
+            Some(b) if b.is_ascii_digit() => {
+                return Err(ParseError {
+                    pos: start,
+                    message: "only tags `#6` and any item `#` are supported",
+                })
+            }
+            _ => return Ok(Type2::Any),
+        }
+        let tag = if self.starts_with(".") {
+            self.pos += 1;
+            Some(
+                self.uint()
+                    .ok_or_else(|| self.error("expected a tag number"))?,
+            )
+        } else {
+            None
+        };
+        self.expect("(", "expected `(`")?;
+        let ty = self.ty()?;
+        self.expect(")", "expected `)`")?;
+        Ok(Type2::Tag(tag, Box::new(ty)))
+    }
+
+    fn group(&mut self) -> Parsed<Group> {
+        let mut choices = vec![self.entries()?];
+        while self.eat("//") {
+            choices.push(self.entries()?);
+        }
+        Ok(Group { choices })
+    }
+
+    /// Reads entries separated by optional commas up to the end of the group.
+    fn entries(&mut self) -> Parsed<Vec<Entry>> {
+        let mut entries = Vec::new();
+        loop {
+            self.skip_ws();
+            if matches!(self.peek(), None | Some(b')' | b']' | b'}'))
+                || self.starts_with("//")
+                || self.at_rule_end()
+            {
+                return Ok(entries);
+            }
+            entries.push(self.entry()?);
+            self.eat(",");
+        }
+    }
+
+    fn entry(&mut self) -> Parsed<Entry> {
+        let start = self.pos;
+        let (min, max) = self.occurrence();
+        let kind = self.entry_kind()?;
+        Ok(Entry {
+            min,
+            max,
+            kind,
+            text: self.src[start..self.pos].to_string(),
+        })
+    }
+
+    /// Reads `?`, `*`, `+` or `n*m`, where both bounds are optional, and defaults to exactly once.
+    fn occurrence(&mut self) -> (usize, usize) {
+        match self.peek() {
+            Some(b'?') => {
+                self.pos += 1;
+                return (0, 1);
+            }
+            Some(b'+') => {
+                self.pos += 1;
+                return (1, usize::MAX);
+            }
+            _ => {}
+        }
+        let start = self.pos;
+        let min = self.uint();
+        if self.peek() == Some(b'*') {
+            self.pos += 1;
+            let max = self.uint();
+            return (
+                min.map_or(0, |n| n as usize),
+                max.map_or(usize::MAX, |n| n as usize),
+            );
+        }
+        self.pos = start;
+        (1, 1)
+    }
+
+    fn entry_kind(&mut self) -> Parsed<EntryKind> {
+        self.skip_ws();
+        let start = self.pos;
+        if let Some(name) = self.name() {
+            if self.eat(":") {
+                return Ok(EntryKind::Member(Some(Key::Name(name)), self.ty()?));
+            }
+        }
+        self.pos = start;
+        let type_err = match self.ty() {
+            Ok(ty) => {
+                let end = self.pos;
+                if self.eat("=>") {
+                    return Ok(EntryKind::Member(Some(Key::Type(ty)), self.ty()?));
+                }
+                if is_literal(&ty) && self.eat(":") {
+                    return Ok(EntryKind::Member(Some(Key::Literal(ty)), self.ty()?));
+                }
+                self.pos = end;
+                return Ok(EntryKind::Member(None, ty));
+            }
+            Err(err) => err,
+        };
+        // `(` starts a group if its contents are not a type
+        self.pos = start;
+        if !self.starts_with("(") {
+            return Err(type_err);
+        }
+        self.nested(|p| {
+            p.pos += 1;
+            let group = p.group().map_err(|err| err.further(type_err))?;
+            p.expect(")", "expected `)`")?;
+            Ok(EntryKind::Group(group))
+        })
+    }
+}
+
+impl ParseError {
+    /// The error that got further, which is the more useful one when trying alternatives.
+    fn further(self, other: Self) -> Self {
+        if other.pos > self.pos {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Returns `true` if `ty` is a single literal, which can be followed by `:` as a map key.
+fn is_literal(ty: &Type) -> bool {
+    matches!(
+        ty.choices.as_slice(),
+        [Type1 {
+            base: Type2::Int(_) | Type2::Float(_) | Type2::Text(_) | Type2::Bytes(_),
+            op: None,
+        }]
+    )
+}
+
+fn type_names<'s>(ty: &'s Type, names: &mut Vec<&'s str>) {
+    for choice in &ty.choices {
+        type2_names(&choice.base, names);
+        if let Some((_, arg)) = &choice.op {
+            type2_names(arg, names);
+        }
+    }
+}
+
+fn type2_names<'s>(ty: &'s Type2, names: &mut Vec<&'s str>) {
+    match ty {
+        Type2::Name(name) => names.push(name),
+        Type2::Paren(ty) => type_names(ty, names),
+        Type2::Tag(_, ty) => type_names(ty, names),
+        Type2::Array(group) | Type2::Map(group) => group_names(group, names),
+        _ => {}
+    }
+}
+
+fn group_names<'s>(group: &'s Group, names: &mut Vec<&'s str>) {
+    for entry in group.choices.iter().flatten() {
+        match &entry.kind {
+            EntryKind::Member(key, ty) => {
+                if let Some(Key::Type(key)) = key {
+                    type_names(key, names);
+                }
+                type_names(ty, names);
+            }
+            EntryKind::Group(group) => group_names(group, names),
+        }
+    }
+}
+
+/// A number of the data or the schema.
+#[derive(Clone, Copy, Debug)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+fn literal(ty: &Type2) -> Option<Number> {
+    match ty {
+        Type2::Int(n) => Some(Number::Int(*n)),
+        Type2::Float(f) => Some(Number::Float(*f)),
+        _ => None,
+    }
+}
+
+/// Reads `item` if it is an integer or a float.
+fn number(item: CborRef<'_>) -> Result<Option<Number>> {
+    Ok(match read_token(&mut SliceReader::new(item.as_bytes()))? {
+        Token::UInt(n) => Some(Number::Int(n.into())),
+        Token::NInt(n) => Some(Number::Int(-1 - i128::from(n))),
+        Token::Float(f) => Some(Number::Float(f)),
+        _ => None,
+    })
+}
+
+fn same_kind(a: Number, b: Number) -> bool {
+    matches!(
+        (a, b),
+        (Number::Int(_), Number::Int(_)) | (Number::Float(_), Number::Float(_))
+    )
+}
+
+fn compare(a: Number, b: Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => Some(a.cmp(&b)),
+        (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+        (Number::Int(a), Number::Float(b)) => (a as f64).partial_cmp(&b),
+        (Number::Float(a), Number::Int(b)) => a.partial_cmp(&(b as f64)),
+    }
+}
+
+/// Checks that `value` is an integer in an integer range or a float in a float range.
+fn in_range(value: Number, lower: &Type2, upper: &Type2, inclusive: bool) -> bool {
+    let (lower, upper) = match (literal(lower), literal(upper)) {
+        (Some(lower), Some(upper)) if same_kind(value, lower) => (lower, upper),
+        _ => return false,
+    };
+    let above = matches!(
+        compare(value, lower),
+        Some(Ordering::Greater | Ordering::Equal)
+    );
+    let below = match compare(value, upper) {
+        Some(Ordering::Less) => true,
+        Some(Ordering::Equal) => inclusive,
+        _ => false,
+    };
+    above && below
+}
+
+/// Checks the size of a string against the argument of `.size`, a number or ranges of numbers.
+fn size_matches(arg: &Type2, size: i128) -> bool {
+    match arg {
+        Type2::Int(n) => *n == size,
+        Type2::Paren(ty) => ty.choices.iter().any(|choice| match &choice.op {
+            None => size_matches(&choice.base, size),
+            Some((Op::Range(inclusive), upper)) => {
+                in_range(Number::Int(size), &choice.base, upper, *inclusive)
+            }
+            Some(_) => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Checks `item` against a type of the prelude.
+fn prelude(name: &str, item: CborRef<'_>) -> Result<bool> {
+    let kind = item.kind();
+    let head = item.as_bytes()[0];
+    let integer = matches!(kind, Kind::UnsignedInteger | Kind::NegativeInteger);
+    let ok = match name {
+        "any" => true,
+        "uint" | "unsigned" => kind == Kind::UnsignedInteger,
+        "nint" | "negative" => kind == Kind::NegativeInteger,
+        "int" | "integer" => integer,
+        "number" => integer || kind == Kind::Float,
+        "bstr" | "bytes" => kind == Kind::Bytes,
+        "tstr" | "text" => kind == Kind::String,
+        "tdate" => match item.tag()? {
+            Some((0, item)) => item.kind() == Kind::String,
+            _ => false,
+        },
+        "time" => match item.tag()? {
+            Some((1, item)) => prelude("number", item)?,
+            _ => false,
+        },
+        "float16" => head == 0xf9,
+        "float32" => head == 0xfa,
+        "float64" => head == 0xfb,
+        "float16-32" => matches!(head, 0xf9 | 0xfa),
+        "float32-64" => matches!(head, 0xfa | 0xfb),
+        "float" => kind == Kind::Float,
+        "false" => head == 0xf4,
+        "true" => head == 0xf5,
+        "bool" => matches!(head, 0xf4 | 0xf5),
+        "nil" | "null" => head == 0xf6,
+        "undefined" => head == 0xf7,
+        _ => return Err(InvalidCddl(format!("undefined rule `{}`", name)).into()),
+    };
+    Ok(ok)
+}
+
+/// Keeps the mismatch with the longest path, which is the most specific one, preferring later
+/// ones on ties.
+fn keep_deepest(best: &mut Option<SchemaMismatch>, mismatch: SchemaMismatch) {
+    if best
+        .as_ref()
+        .is_some_and(|kept| kept.path.len() > mismatch.path.len())
+    {
+        return;
+    }
+    *best = Some(mismatch);
+}
+
+/// The result of checking an item against a schema type, if the item itself is well-formed.
+type Outcome = core::result::Result<(), Mismatch>;
+
+/// Why an item does not match a schema type.
+struct Mismatch {
+    mismatch: SchemaMismatch,
+    /// Whether the mismatch is about a part of the item, such as a missing map entry, rather
+    /// than the item as a whole, which makes it more specific even at the same path.
+    partial: bool,
+}
+
+struct Validator<'s> {
+    schema: &'s Schema,
+    /// The path from the validated item to the current one.
+    path: Vec<PathSegment>,
+    /// The rules the current type is nested in.
+    rules: Vec<&'s str>,
+}
+
+impl<'s> Validator<'s> {
+    fn mismatch(&self, expected: &str) -> SchemaMismatch {
+        SchemaMismatch {
+            path: self.path.clone(),
+            rule: self.rules.last().copied().unwrap_or_default().to_string(),
+            expected: expected.to_string(),
+        }
+    }
+
+    /// A mismatch of the current item as a whole.
+    fn whole(&self, expected: &str) -> Mismatch {
+        Mismatch {
+            mismatch: self.mismatch(expected),
+            partial: false,
+        }
+    }
+
+    /// The most specific mismatch within the current item, or else one of the item as a whole.
+    fn within(&self, best: Option<SchemaMismatch>, expected: &str) -> Mismatch {
+        match best {
+            Some(mismatch) => Mismatch {
+                mismatch,
+                partial: true,
+            },
+            None => self.whole(expected),
+        }
+    }
+
+    /// Runs `f` within the rule `name`.
+    fn in_rule<T>(&mut self, name: &'s str, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.rules.len() >= MAX_DEPTH {
+            return Err(ValidationTooDeep(MAX_DEPTH).into());
+        }
+        self.rules.push(name);
+        let result = f(self);
+        self.rules.pop();
+        result
+    }
+
+    /// Returns the group rule `ty` refers to, if it is a single name of one.
+    fn group_rule(&self, ty: &'s Type) -> Option<(&'s str, &'s Group)> {
+        let schema = self.schema;
+        match ty.choices.as_slice() {
+            [Type1 {
+                base: Type2::Name(name),
+                op: None,
+            }] => match schema.rules.get_key_value(name) {
+                Some((name, Rule::Group(group))) => Some((name.as_str(), group)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn check_type(&mut self, ty: &'s Type, item: CborRef<'_>) -> Result<Outcome> {
+        let mut best = None;
+        for choice in &ty.choices {
+            match self.check_type1(choice, &ty.text, item)? {
+                Ok(()) => return Ok(Ok(())),
+                // only mismatches of nested items or parts of the item are more specific than
+                // the whole type
+                Err(Mismatch { mismatch, partial })
+                    if partial || mismatch.path.len() > self.path.len() =>
+                {
+                    keep_deepest(&mut best, mismatch)
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(Err(self.within(best, &ty.text)))
+    }
+
+    fn check_type1(
+        &mut self,
+        ty: &'s Type1,
+        expected: &'s str,
+        item: CborRef<'_>,
+    ) -> Result<Outcome> {
+        let (op, arg) = match &ty.op {
+            None | Some((Op::Default, _)) => return self.check_type2(&ty.base, expected, item),
+            Some((op, arg)) => (*op, arg),
+        };
+        let ok = match op {
+            Op::Range(inclusive) => match number(item)? {
+                Some(value) => in_range(value, &ty.base, arg, inclusive),
+                None => false,
+            },
+            _ => {
+                if let Err(mismatch) = self.check_type2(&ty.base, expected, item)? {
+                    return Ok(Err(mismatch));
+                }
+                check_control(op, arg, item)?
+            }
+        };
+        Ok(if ok {
+            Ok(())
+        } else {
+            Err(self.whole(expected))
+        })
+    }
+
+    fn check_type2(
+        &mut self,
+        ty: &'s Type2,
+        expected: &'s str,
+        item: CborRef<'_>,
+    ) -> Result<Outcome> {
+        let ok = match ty {
+            Type2::Int(_) | Type2::Float(_) => match (literal(ty), number(item)?) {
+                (Some(a), Some(b)) => same_kind(a, b) && compare(a, b) == Some(Ordering::Equal),
+                _ => false,
+            },
+            Type2::Text(text) => item.kind() == Kind::String && item.decode::<String>()? == *text,
+            Type2::Bytes(bytes) => {
+                item.kind() == Kind::Bytes && *item.decode::<Box<[u8]>>()? == bytes[..]
+            }
+            Type2::Name(name) => return self.check_name(name, expected, item),
+            Type2::Paren(ty) => return self.check_type(ty, item),
+            Type2::Array(group) => return self.check_array(group, expected, item),
+            Type2::Map(group) => return self.check_map(group, expected, item),
+            Type2::Tag(tag, ty) => match item.tag()? {
+                Some((found, item)) if tag.is_none() || *tag == Some(found) => {
+                    return self.check_type(ty, item)
+                }
+                _ => false,
+            },
+            Type2::Any => true,
+        };
+        Ok(if ok {
+            Ok(())
+        } else {
+            Err(self.whole(expected))
+        })
+    }
+
+    fn check_name(
+        &mut self,
+        name: &'s str,
+        expected: &'s str,
+        item: CborRef<'_>,
+    ) -> Result<Outcome> {
+        let schema = self.schema;
+        match schema.rules.get_key_value(name) {
+            Some((name, Rule::Type(ty))) => self.in_rule(name, |v| v.check_type(ty, item)),
+            Some((name, Rule::Group(_))) => {
+                Err(InvalidCddl(format!("group `{}` used as a type", name)).into())
+            }
+            None => Ok(if prelude(name, item)? {
+                Ok(())
+            } else {
+                Err(self.whole(expected))
+            }),
+        }
+    }
+
+    fn check_array(
+        &mut self,
+        group: &'s Group,
+        expected: &'s str,
+        item: CborRef<'_>,
+    ) -> Result<Outcome> {
+        if item.kind() != Kind::Array {
+            return Ok(Err(self.whole(expected)));
+        }
+        let items = item.elements()?.collect::<Result<Vec<_>>>()?;
+        let mut best = None;
+        let ends = self.group_ends(group, &items, BTreeSet::from([0]), &mut best)?;
+        if ends.contains(&items.len()) {
+            return Ok(Ok(()));
+        }
+        Ok(Err(self.within(best, expected)))
+    }
+
+    /// Returns every index of `items` a match of `group` starting at one of `starts` can end at.
+    fn group_ends(
+        &mut self,
+        group: &'s Group,
+        items: &[CborRef<'_>],
+        starts: BTreeSet<usize>,
+        best: &mut Option<SchemaMismatch>,
+    ) -> Result<BTreeSet<usize>> {
+        let mut ends = BTreeSet::new();
+        for entries in &group.choices {
+            let mut positions = starts.clone();
+            for entry in entries {
+                if positions.is_empty() {
+                    break;
+                }
+                positions = self.entry_ends(entry, items, positions, best)?;
+            }
+            ends.extend(positions);
+        }
+        Ok(ends)
+    }
+
+    /// Returns every index of `items` a match of `entry` with any allowed number of
+    /// occurrences starting at one of `starts` can end at.
+    fn entry_ends(
+        &mut self,
+        entry: &'s Entry,
+        items: &[CborRef<'_>],
+        starts: BTreeSet<usize>,
+        best: &mut Option<SchemaMismatch>,
+    ) -> Result<BTreeSet<usize>> {
+        let mut ends = BTreeSet::new();
+        let mut current = starts;
+        let mut count = 0;
+        loop {
+            if count >= entry.min {
+                // continuing from a position reached before can't reach anything new
+                current.retain(|pos| ends.insert(*pos));
+            }
+            if current.is_empty() || count == entry.max {
+                return Ok(ends);
+            }
+            let mut next = BTreeSet::new();
+            for pos in current {
+                next.extend(self.entry_once(entry, items, pos, best)?);
+            }
+            current = next;
+            count += 1;
+        }
+    }
+
+    /// Returns every index of `items` a single occurrence of `entry` at `pos` can end at.
+    fn entry_once(
+        &mut self,
+        entry: &'s Entry,
+        items: &[CborRef<'_>],
+        pos: usize,
+        best: &mut Option<SchemaMismatch>,
+    ) -> Result<BTreeSet<usize>> {
+        let ty = match &entry.kind {
+            EntryKind::Group(group) => {
+                return self.group_ends(group, items, BTreeSet::from([pos]), best)
+            }
+            // keys only document the elements of arrays
+            EntryKind::Member(key, ty) => match (key, self.group_rule(ty)) {
+                (None, Some((name, group))) => {
+                    return self.in_rule(name, |v| {
+                        v.group_ends(group, items, BTreeSet::from([pos]), best)
+                    })
+                }
+                _ => ty,
+            },
+        };
+        let mut ends = BTreeSet::new();
+        if let Some(item) = items.get(pos) {
+            self.path.push(PathSegment::Index(pos));
+            let outcome = self.check_type(ty, *item);
+            self.path.pop();
+            match outcome? {
+                Ok(()) => {
+                    ends.insert(pos + 1);
+                }
+                Err(Mismatch { mismatch, .. }) => keep_deepest(best, mismatch),
+            }
+        }
+        Ok(ends)
+    }
+
+    fn check_map(
+        &mut self,
+        group: &'s Group,
+        expected: &'s str,
+        item: CborRef<'_>,
+    ) -> Result<Outcome> {
+        if item.kind() != Kind::Map {
+            return Ok(Err(self.whole(expected)));
+        }
+        let entries = item.entries()?.collect::<Result<Vec<_>>>()?;
+        let mut used = vec![false; entries.len()];
+        let mut best = None;
+        if self.map_group(group, &entries, &mut used, Some(expected), &mut best)? {
+            return Ok(Ok(()));
+        }
+        Ok(Err(self.within(best, expected)))
+    }
+
+    /// Matches one of the choices of `group` against the entries that are not `used` yet,
+    /// marking the ones it consumes.
+    ///
+    /// Entries are consumed greedily in the order of the schema. With `complete`, the expected
+    /// type of the whole map, a choice only matches if it consumes all entries.
+    fn map_group(
+        &mut self,
+        group: &'s Group,
+        entries: &[(CborRef<'_>, CborRef<'_>)],
+        used: &mut [bool],
+        complete: Option<&str>,
+        best: &mut Option<SchemaMismatch>,
+    ) -> Result<bool> {
+        'choices: for choice in &group.choices {
+            let mut tried = used.to_vec();
+            for entry in choice {
+                if !self.map_entry(entry, entries, &mut tried, best)? {
+                    continue 'choices;
+                }
+            }
+            match (complete, tried.iter().position(|used| !used)) {
+                (Some(expected), Some(i)) => {
+                    // a value that didn't match explains a left over entry better
+                    let mut mismatch = self.mismatch(expected);
+                    mismatch
+                        .path
+                        .push(PathSegment::Key(entries[i].0.as_bytes().into()));
+                    if best
+                        .as_ref()
+                        .is_none_or(|kept| kept.path.len() < mismatch.path.len())
+                    {
+                        *best = Some(mismatch);
+                    }
+                }
+                _ => {
+                    used.copy_from_slice(&tried);
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn map_entry(
+        &mut self,
+        entry: &'s Entry,
+        entries: &[(CborRef<'_>, CborRef<'_>)],
+        used: &mut [bool],
+        best: &mut Option<SchemaMismatch>,
+    ) -> Result<bool> {
+        let (key, ty) = match &entry.kind {
+            EntryKind::Group(group) => return self.map_repeat(entry, group, entries, used, best),
+            EntryKind::Member(Some(key), ty) => (key, ty),
+            EntryKind::Member(None, ty) => match self.group_rule(ty) {
+                Some((name, group)) => {
+                    return self.in_rule(name, |v| v.map_repeat(entry, group, entries, used, best))
+                }
+                None => {
+                    let message = format!("map entry `{}` has no key", entry.text);
+                    return Err(InvalidCddl(message).into());
+                }
+            },
+        };
+        let mut count = 0;
+        for (i, (k, v)) in entries.iter().enumerate() {
+            if count == entry.max {
+                break;
+            }
+            if used[i] || !self.key_matches(key, *k)? {
+                continue;
+            }
+            self.path.push(PathSegment::Key(k.as_bytes().into()));
+            let outcome = self.check_type(ty, *v);
+            self.path.pop();
+            match outcome? {
+                Ok(()) => {
+                    used[i] = true;
+                    count += 1;
+                }
+                Err(Mismatch { mismatch, .. }) => {
+                    keep_deepest(best, mismatch);
+                    if key.is_cut() {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        if count < entry.min {
+            keep_deepest(best, self.mismatch(&entry.text));
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Matches an inline or named group as often as the occurrence of `entry` allows.
+    fn map_repeat(
+        &mut self,
+        entry: &'s Entry,
+        group: &'s Group,
+        entries: &[(CborRef<'_>, CborRef<'_>)],
+        used: &mut [bool],
+        best: &mut Option<SchemaMismatch>,
+    ) -> Result<bool> {
+        fn consumed(used: &[bool]) -> usize {
+            used.iter().filter(|used| **used).count()
+        }
+
+        let mut count = 0;
+        while count < entry.max {
+            let before = consumed(used);
+            if !self.map_group(group, entries, used, None, best)? {
+                break;
+            }
+            count += 1;
+            // a group that matches without consuming anything matches any number of times
+            if consumed(used) == before {
+                return Ok(true);
+            }
+        }
+        if count < entry.min {
+            keep_deepest(best, self.mismatch(&entry.text));
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn key_matches(&mut self, key: &'s Key, item: CborRef<'_>) -> Result<bool> {
+        match key {
+            Key::Name(name) => Ok(item.kind() == Kind::String && item.decode::<String>()? == *name),
+            Key::Literal(ty) | Key::Type(ty) => Ok(self.check_type(ty, item)?.is_ok()),
+        }
+    }
+}
+
+/// Checks the control operator `op` with argument `arg` for an item that matched its base type.
+fn check_control(op: Op, arg: &Type2, item: CborRef<'_>) -> Result<bool> {
+    if op == Op::Size {
+        let ok = match item.kind() {
+            Kind::Bytes | Kind::String => size_matches(arg, item.len()?.unwrap_or(0) as i128),
+            // the number of bytes the value fits in
+            Kind::UnsignedInteger => match (arg, number(item)?) {
+                (Type2::Int(size), Some(Number::Int(value))) => match u32::try_from(*size) {
+                    Ok(size) if size < 16 => value < (1 << (8 * size)),
+                    Ok(_) => true,
+                    Err(_) => false,
+                },
+                _ => false,
+            },
+            _ => false,
+        };
+        return Ok(ok);
+    }
+    let ordering = match (number(item)?, literal(arg)) {
+        (Some(value), Some(arg)) => compare(value, arg),
+        _ => None,
+    };
+    Ok(match op {
+        Op::Lt => ordering == Some(Ordering::Less),
+        Op::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        Op::Gt => ordering == Some(Ordering::Greater),
+        Op::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        Op::Eq => ordering == Some(Ordering::Equal),
+        Op::Ne => ordering != Some(Ordering::Equal),
+        _ => false,
+    })
+}
+
+/// A set of cddl rules.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    rules: BTreeMap<String, Rule>,
+}
+
+impl Schema {
+    /// Parses the rules in `src`.
+    ///
+    /// Fails with [`InvalidCddl`] if the rules can't be parsed, use unsupported features, nest
+    /// types more than 128 levels deep, define a name twice or refer to a name that is neither
+    /// defined nor part of the prelude.
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser {
+            src,
+            pos: 0,
+            depth: 0,
+        };
+        let parsed = parser.rules().map_err(|err| invalid(src, err))?;
+        let mut rules = BTreeMap::new();
+        for (name, rule) in parsed {
+            if rules.contains_key(&name) {
+                return Err(InvalidCddl(format!("rule `{}` is defined twice", name)).into());
+            }
+            rules.insert(name, rule);
+        }
+        let mut names = Vec::new();
+        for rule in rules.values() {
+            match rule {
+                Rule::Type(ty) => type_names(ty, &mut names),
+                Rule::Group(group) => group_names(group, &mut names),
+            }
+        }
+        if let Some(name) = names
+            .into_iter()
+            .find(|name| !rules.contains_key(*name) && !PRELUDE.contains(name))
+        {
+            return Err(InvalidCddl(format!("undefined rule `{}`", name)).into());
+        }
+        Ok(Self { rules })
+    }
+
+    /// Checks the first item of `bytes` against the type rule `rule`. Trailing bytes are
+    /// ignored.
+    ///
+    /// Fails with a [`SchemaMismatch`] naming the path to the most deeply nested item that does
+    /// not match and the rule and type it was checked against. Fails with [`InvalidCddl`] if
+    /// `rule` is not a type rule of the schema, and with [`ValidationTooDeep`] if checking the
+    /// item nests more than 128 rules, which happens for items nested that deeply under a
+    /// recursive rule.
+    pub fn validate(&self, rule: &str, bytes: &[u8]) -> Result<()> {
+        let (name, ty) = match self.rules.get_key_value(rule) {
+            Some((name, Rule::Type(ty))) => (name, ty),
+            Some(_) => return Err(InvalidCddl(format!("`{}` is a group", rule)).into()),
+            None => return Err(InvalidCddl(format!("undefined rule `{}`", rule)).into()),
+        };
+        let item = CborRef::new(bytes)?;
+        let mut validator = Validator {
+            schema: self,
+            path: Vec::new(),
+            rules: vec![name.as_str()],
+        };
+        validator
+            .check_type(ty, item)?
+            .map_err(|Mismatch { mismatch, .. }| mismatch.into())
+    }
+}
+
+/// Turns a parse error into an [`InvalidCddl`] error with the line and column it occurred at.
+fn invalid(src: &str, err: ParseError) -> Error {
+    let before = &src[..err.pos];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    let message = format!("{} at line {}, column {}", err.message, line, column);
+    InvalidCddl(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::CborError, RawCborCodec, CBOR};
+    use libipld_core::{cid::Cid, codec::Codec, ipld::Ipld, multihash::MultihashDigest};
+    use libipld_macro::ipld;
+    use multihash::Code;
+
+    const FILES: &str = r#"
+        ; a file system tree
+        directory = {
+            name: tstr,
+            ? mode: uint .size 2,
+            entries: [* entry],
+        }
+        entry = file / directory
+        file = [name: tstr, size: uint, content: link]
+        link = #6.42(bstr)
+    "#;
+
+    fn mismatch(schema: &Schema, rule: &str, value: &Ipld) -> SchemaMismatch {
+        let bytes = RawCborCodec.encode(value).unwrap();
+        let err = schema.validate(rule, &bytes).unwrap_err();
+        err.downcast::<SchemaMismatch>().unwrap()
+    }
+
+    #[test]
+    fn links() {
+        let schema = Schema::parse(FILES).unwrap();
+        let cid = Cid::new_v1(CBOR, Code::Sha2_256.digest(b"file"));
+        let root = ipld!({
+            "name": "root",
+            "mode": 493,
+            "entries": [["a.txt", 3, cid], { "name": "sub", "entries": [] }],
+        });
+        let bytes = RawCborCodec.encode(&root).unwrap();
+        schema.validate("directory", &bytes).unwrap();
+
+        let root = ipld!({ "name": "root", "entries": [["a.txt", 3, vec![1u8]]] });
+        let m = mismatch(&schema, "directory", &root);
+        assert_eq!(
+            m.path,
+            vec![PathSegment::from("entries"), 0.into(), 2.into()]
+        );
+        assert_eq!((m.rule.as_str(), m.expected.as_str()), ("file", "link"));
+
+        let root = ipld!({ "name": "root", "mode": 70000, "entries": [] });
+        let m = mismatch(&schema, "directory", &root);
+        assert_eq!(m.path, vec![PathSegment::from("mode")]);
+        assert_eq!(m.expected, "uint .size 2");
+        let bytes = RawCborCodec.encode(&root).unwrap();
+        let err = schema.validate("directory", &bytes).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Item does not match `uint .size 2` in rule `directory` at path [\"mode\"]."
+        );
+
+        let m = mismatch(&schema, "directory", &ipld!({ "entries": [] }));
+        assert!(m.path.is_empty());
+        assert_eq!(m.expected, "name: tstr");
+
+        let root = ipld!({ "name": "root", "entries": [], "owner": "me" });
+        let m = mismatch(&schema, "directory", &root);
+        assert_eq!(m.path, vec![PathSegment::from("owner")]);
+        assert_eq!(m.rule, "directory");
+    }
+
+    #[test]
+    fn non_string_keys() {
+        let schema = Schema::parse(
+            r#"
+            grid = { * [uint, uint] => cell }
+            cell = "empty" / "wall"
+            header = { 1: uint, ? 2: tstr, * uint => any }
+            message = header / tstr
+            "#,
+        )
+        .unwrap();
+        let mut grid = BTreeMap::new();
+        grid.insert((0u8, 0u8), "empty".to_string());
+        grid.insert((0u8, 1u8), "wall".to_string());
+        let bytes = RawCborCodec.encode(&grid).unwrap();
+        schema.validate("grid", &bytes).unwrap();
+        grid.insert((1u8, 0u8), "door".to_string());
+        let bytes = RawCborCodec.encode(&grid).unwrap();
+        let err = schema.validate("grid", &bytes).unwrap_err();
+        assert_eq!(
+            CborError::from(&err),
+            CborError::SchemaMismatch {
+                path: vec![PathSegment::key(&(1u8, 0u8)).unwrap()],
+                rule: "grid".into(),
+                expected: "cell".into(),
+            }
+        );
+
+        let mut header = BTreeMap::new();
+        header.insert(1u64, Ipld::Integer(7));
+        header.insert(2u64, Ipld::String("x".into()));
+        header.insert(5u64, Ipld::Bool(true));
+        let bytes = RawCborCodec.encode(&header).unwrap();
+        schema.validate("header", &bytes).unwrap();
+        header.remove(&1);
+        let bytes = RawCborCodec.encode(&header).unwrap();
+        let err = schema.validate("header", &bytes).unwrap_err();
+        let m = err.downcast::<SchemaMismatch>().unwrap();
+        assert_eq!((m.path.len(), m.expected.as_str()), (0, "1: uint"));
+        // the missing entry is also reported through a rule, but a wrong kind of item is not
+        let err = schema.validate("message", &bytes).unwrap_err();
+        let m = err.downcast::<SchemaMismatch>().unwrap();
+        assert_eq!(
+            (m.rule.as_str(), m.expected.as_str()),
+            ("header", "1: uint")
+        );
+        let err = schema.validate("message", &[0x01]).unwrap_err();
+        let m = err.downcast::<SchemaMismatch>().unwrap();
+        assert_eq!(
+            (m.rule.as_str(), m.expected.as_str()),
+            ("message", "header / tstr")
+        );
+        // a key written with `:` implies a cut, so its entry can't match `* uint => any` instead
+        header.insert(1u64, Ipld::Integer(7));
+        header.insert(2u64, Ipld::Bool(true));
+        let bytes = RawCborCodec.encode(&header).unwrap();
+        let err = schema.validate("header", &bytes).unwrap_err();
+        let m = err.downcast::<SchemaMismatch>().unwrap();
+        assert_eq!(m.path, vec![PathSegment::key(&2u64).unwrap()]);
+        assert_eq!(m.expected, "tstr");
+    }
+
+    #[test]
+    fn groups_and_controls() {
+        let schema = Schema::parse(
+            r#"
+            pair = (int, int)
+            line = [pair, pair]
+            polygon = [3* pair]
+            rgb = [0..255, 0..255, 0..255] / (h'00' / h'ff')
+            temperature = float .ge -273.15 / { kelvin: uint // celsius: int }
+            "#,
+        )
+        .unwrap();
+        let valid = |rule: &str, value: Ipld| {
+            let bytes = RawCborCodec.encode(&value).unwrap();
+            schema.validate(rule, &bytes).is_ok()
+        };
+        assert!(valid("line", ipld!([1, 2, 3, -4])));
+        assert!(!valid("line", ipld!([1, 2, 3])));
+        assert!(valid("polygon", ipld!([0, 0, 1, 0, 1, 1])));
+        assert!(!valid("polygon", ipld!([0, 0, 1, 0])));
+        assert!(valid("rgb", ipld!([0, 128, 255])));
+        assert!(!valid("rgb", ipld!([0, 128, 256])));
+        assert!(valid("rgb", ipld!(vec![0xffu8])));
+        assert!(valid("temperature", ipld!(20.5)));
+        assert!(!valid("temperature", ipld!(-300.0)));
+        assert!(valid("temperature", ipld!({ "kelvin": 300 })));
+        assert!(valid("temperature", ipld!({ "celsius": -20 })));
+        assert!(!valid(
+            "temperature",
+            ipld!({ "kelvin": 300, "celsius": 27 })
+        ));
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        for (src, message) in [
+            ("a = b", "undefined rule `b`"),
+            ("a = int\na = tstr", "rule `a` is defined twice"),
+            ("a = [int", "expected `]` at line 1, column 9"),
+            (
+                "a = int\nb = tstr .regexp \"x\"",
+                "unsupported control operator at line 2, column 10",
+            ),
+            (
+                "a = #2(int)",
+                "only tags `#6` and any item `#` are supported at line 1, column 5",
+            ),
+            ("a = int\nb /= tstr", "expected `=` at line 2, column 3"),
+        ] {
+            let err = Schema::parse(src).unwrap_err();
+            assert_eq!(err.downcast::<InvalidCddl>().unwrap().0, message);
+        }
+        let src = format!("a = {}uint{}", "[".repeat(200_000), "]".repeat(200_000));
+        let err = Schema::parse(&src).unwrap_err();
+        assert_eq!(
+            err.downcast::<InvalidCddl>().unwrap().0,
+            "types nested deeper than 128 levels at line 1, column 133"
+        );
+        // rules may recurse as deeply as the data, up to the limit
+        let schema = Schema::parse("r = [* r] / uint").unwrap();
+        let nested = |depth: usize| [vec![0x81; depth], vec![0x00]].concat();
+        schema.validate("r", &nested(100)).unwrap();
+        let err = schema.validate("r", &nested(200)).unwrap_err();
+        assert_eq!(err.downcast::<ValidationTooDeep>().unwrap().0, MAX_DEPTH);
+
+        let schema = Schema::parse("a = { int }\nb = (c: int)").unwrap();
+        assert!(schema.validate("a", &[0xa1, 0x01, 0x01]).is_err());
+        assert!(schema.validate("b", &[0x01]).is_err());
+        assert!(schema.validate("c", &[0x01]).is_err());
+    }
+}
@@ -0,0 +1,467 @@
+//! Checking and converting data for dag-cbor compatibility.
+//!
+//! Dag-cbor is a strict subset of cbor: map keys must be strings sorted length-first, floats must
+//! be 64 bit, the only allowed tag is 42, lengths must be definite and all heads must be minimal.
+use crate::{
+    decode::{f16_to_f64, read_head, read_link, read_u8},
+    encode::write_u64,
+    error::{NotDagCbor, UnexpectedCode, UnexpectedEof},
+    RawCborCodec,
+};
+use libipld_core::{codec::Encode, error::Result, ipld::Ipld};
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{Cursor, Seek, SeekFrom},
+};
+
+/// The reason why an item is not valid dag-cbor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A map key is not a text string.
+    NonStringKey,
+    /// Map keys are not sorted length-first.
+    UnsortedKeys,
+    /// A map key occurs more than once.
+    DuplicateKey,
+    /// A float is encoded with 16 or 32 bits.
+    NonCanonicalFloat,
+    /// A float is NaN or infinite.
+    NonFiniteFloat,
+    /// A tag other than 42.
+    UnsupportedTag(u64),
+    /// A simple value other than true, false or null.
+    UnsupportedSimple(u8),
+    /// A string, array or map with indefinite length.
+    IndefiniteLength,
+    /// A head that is longer than needed for its argument.
+    NonMinimalHead,
+}
+
+impl ViolationKind {
+    /// Returns `true` if [`to_dag_cbor`] can fix this by re-encoding without losing information.
+    pub fn is_convertible(&self) -> bool {
+        matches!(
+            self,
+            Self::UnsortedKeys
+                | Self::NonCanonicalFloat
+                | Self::IndefiniteLength
+                | Self::NonMinimalHead
+        )
+    }
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NonStringKey => write!(f, "map key is not a string"),
+            Self::UnsortedKeys => write!(f, "map keys are not sorted length-first"),
+            Self::DuplicateKey => write!(f, "duplicate map key"),
+            Self::NonCanonicalFloat => write!(f, "float is not encoded with 64 bits"),
+            Self::NonFiniteFloat => write!(f, "float is not finite"),
+            Self::UnsupportedTag(tag) => write!(f, "unsupported tag `{}`", tag),
+            Self::UnsupportedSimple(value) => write!(f, "unsupported simple value `{}`", value),
+            Self::IndefiniteLength => write!(f, "indefinite length item"),
+            Self::NonMinimalHead => write!(f, "head is not minimally encoded"),
+        }
+    }
+}
+
+/// A single dag-cbor rule violation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// Byte offset of the offending item.
+    pub offset: u64,
+    /// What is wrong with the item.
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
+/// Returns `true` if a head of `len` bytes is the shortest one for `arg`.
+fn is_minimal(len: usize, arg: u64) -> bool {
+    match len {
+        1 => true,
+        2 => arg > 0x17,
+        3 => arg > 0xff,
+        5 => arg > 0xffff,
+        _ => arg > 0xffff_ffff,
+    }
+}
+
+/// Walks over a single item, collecting violations and writing the canonical dag-cbor form.
+struct Walker<'a> {
+    r: Cursor<&'a [u8]>,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Walker<'a> {
+    fn violation(&mut self, offset: u64, kind: ViolationKind) {
+        self.violations.push(Violation { offset, kind });
+    }
+
+    fn read_slice(&mut self, len: u64) -> Result<&'a [u8]> {
+        let bytes = *self.r.get_ref();
+        let start = self.r.position() as usize;
+        let end = start.checked_add(len as usize).ok_or(UnexpectedEof)?;
+        let slice = bytes.get(start..end).ok_or(UnexpectedEof)?;
+        self.r.set_position(end as u64);
+        Ok(slice)
+    }
+
+    /// Calls `f` for every item of a container with `len` items, or until the break code.
+    fn items(
+        &mut self,
+        len: Option<u64>,
+        mut f: impl FnMut(&mut Self) -> Result<()>,
+    ) -> Result<()> {
+        match len {
+            Some(len) => {
+                for _ in 0..len {
+                    f(self)?;
+                }
+            }
+            None => loop {
+                if read_u8(&mut self.r)? == 0xff {
+                    break;
+                }
+                self.r.seek(SeekFrom::Current(-1))?;
+                f(self)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn item(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        let offset = self.r.position();
+        let mut head = [0; 9];
+        let (len, arg) = read_head(&mut self.r, &mut head)?;
+        let major = head[0] >> 5;
+        if let (0..=6, Some(arg)) = (major, arg) {
+            if !is_minimal(len, arg) {
+                self.violation(offset, ViolationKind::NonMinimalHead);
+            }
+        }
+        if arg.is_none() && major != 7 {
+            self.violation(offset, ViolationKind::IndefiniteLength);
+        }
+        match (major, arg) {
+            // Major type 0 and 1: an unsigned or negative integer
+            (0 | 1, Some(n)) => write_u64(out, major, n)?,
+
+            // Major type 2 and 3: a byte or text string
+            (2 | 3, Some(n)) => {
+                let bytes = self.read_slice(n)?;
+                if major == 3 {
+                    std::str::from_utf8(bytes)?;
+                }
+                write_u64(out, major, n)?;
+                out.extend_from_slice(bytes);
+            }
+
+            // Major type 2 and 3: a byte or text string (indefinite length)
+            (2 | 3, None) => {
+                let mut bytes = Vec::new();
+                self.items(None, |this| {
+                    let mut head = [0; 9];
+                    let chunk = read_head(&mut this.r, &mut head)?;
+                    match chunk {
+                        (_, Some(n)) if head[0] >> 5 == major => {
+                            bytes.extend_from_slice(this.read_slice(n)?);
+                            Ok(())
+                        }
+                        _ => Err(UnexpectedCode::new::<Ipld>(head[0]).into()),
+                    }
+                })?;
+                if major == 3 {
+                    std::str::from_utf8(&bytes)?;
+                }
+                write_u64(out, major, bytes.len() as u64)?;
+                out.extend_from_slice(&bytes);
+            }
+
+            // Major type 4: an array of data items
+            (4, len) => {
+                let mut n = 0;
+                let mut items = Vec::new();
+                self.items(len, |this| {
+                    n += 1;
+                    this.item(&mut items)
+                })?;
+                write_u64(out, 4, n)?;
+                out.extend_from_slice(&items);
+            }
+
+            // Major type 5: a map of pairs of data items
+            (5, len) => {
+                let mut entries = Vec::new();
+                let mut keys = BTreeSet::new();
+                let mut prev: Option<Vec<u8>> = None;
+                self.items(len, |this| {
+                    let offset = this.r.position();
+                    if read_u8(&mut this.r)? >> 5 != 3 {
+                        this.violation(offset, ViolationKind::NonStringKey);
+                    }
+                    this.r.set_position(offset);
+                    let mut key = Vec::new();
+                    this.item(&mut key)?;
+                    // keys are compared in canonical form, duplicates against all previous keys
+                    if !keys.insert(key.clone()) {
+                        this.violation(offset, ViolationKind::DuplicateKey);
+                    } else if prev
+                        .as_ref()
+                        .is_some_and(|prev| (prev.len(), prev) > (key.len(), &key))
+                    {
+                        this.violation(offset, ViolationKind::UnsortedKeys);
+                    }
+                    prev = Some(key.clone());
+                    let mut value = Vec::new();
+                    this.item(&mut value)?;
+                    entries.push((key, value));
+                    Ok(())
+                })?;
+                entries.sort_by(|(a, _), (b, _)| (a.len(), a).cmp(&(b.len(), b)));
+                write_u64(out, 5, entries.len() as u64)?;
+                for (key, value) in entries {
+                    out.extend_from_slice(&key);
+                    out.extend_from_slice(&value);
+                }
+            }
+
+            // Major type 6: optional semantic tagging of other major types
+            (6, Some(42)) => {
+                let offset = self.r.position();
+                let (len, arg) = read_head(&mut self.r, &mut head)?;
+                if let (2, Some(arg)) = (head[0] >> 5, arg) {
+                    if !is_minimal(len, arg) {
+                        self.violation(offset, ViolationKind::NonMinimalHead);
+                    }
+                }
+                self.r.set_position(offset);
+                read_link(&mut self.r)?.encode(RawCborCodec, out)?;
+            }
+            (6, Some(tag)) => {
+                self.violation(offset, ViolationKind::UnsupportedTag(tag));
+                write_u64(out, 6, tag)?;
+                self.item(out)?;
+            }
+
+            // Major type 7: floating-point numbers and other simple data types
+            _ => {
+                let float = match (head[0], arg) {
+                    (0xf4..=0xf6, _) => {
+                        out.push(head[0]);
+                        return Ok(());
+                    }
+                    (0xf9, Some(bits)) => f16_to_f64(bits as u16),
+                    (0xfa, Some(bits)) => f32::from_bits(bits as u32).into(),
+                    (0xfb, Some(bits)) => f64::from_bits(bits),
+                    (0xf8, Some(value)) | (0xe0..=0xf7, Some(value)) => {
+                        self.violation(offset, ViolationKind::UnsupportedSimple(value as u8));
+                        out.extend_from_slice(&head[..len]);
+                        return Ok(());
+                    }
+                    _ => return Err(UnexpectedCode::new::<Ipld>(head[0]).into()),
+                };
+                if !float.is_finite() {
+                    self.violation(offset, ViolationKind::NonFiniteFloat);
+                } else if head[0] != 0xfb {
+                    self.violation(offset, ViolationKind::NonCanonicalFloat);
+                }
+                out.push(0xfb);
+                out.extend_from_slice(&float.to_bits().to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Walks the first item in `bytes`, returning the violations and the canonical encoding.
+fn walk(bytes: &[u8]) -> Result<(Vec<Violation>, Vec<u8>)> {
+    let mut walker = Walker {
+        r: Cursor::new(bytes),
+        violations: Vec::new(),
+    };
+    let mut out = Vec::with_capacity(bytes.len());
+    walker.item(&mut out)?;
+    Ok((walker.violations, out))
+}
+
+/// Checks whether the first item in `bytes` satisfies the dag-cbor rules.
+///
+/// Returns all rule violations in the order they occur, so an empty result means the data is
+/// already valid dag-cbor. Fails if the data is not well-formed cbor.
+pub fn check(bytes: &[u8]) -> Result<Vec<Violation>> {
+    Ok(walk(bytes)?.0)
+}
+
+/// Converts the first item in `bytes` to dag-cbor.
+///
+/// Map entries are sorted, floats widened to 64 bit, indefinite lengths made definite and heads
+/// shortened. Fails with [`NotDagCbor`] if the data contains something that can not be
+/// represented in dag-cbor, such as non-string keys or tags other than 42.
+pub fn to_dag_cbor(bytes: &[u8]) -> Result<Vec<u8>> {
+    let (violations, out) = walk(bytes)?;
+    if let Some(violation) = violations.into_iter().find(|v| !v.kind.is_convertible()) {
+        return Err(NotDagCbor(violation).into());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::{cid::Cid, codec::Codec};
+    use libipld_macro::ipld;
+    use multihash::{Code, MultihashDigest};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn valid() {
+        let ipld = ipld!({ "a": 1, "bb": [true, null, "x"], "c": 1.5 });
+        let mut bytes = RawCborCodec.encode(&ipld).unwrap();
+        // this codec writes keys in BTreeMap order and 1.5 as f32
+        assert_eq!(
+            check(&bytes).unwrap(),
+            vec![
+                Violation {
+                    offset: bytes.len() as u64 - 7,
+                    kind: ViolationKind::UnsortedKeys
+                },
+                Violation {
+                    offset: bytes.len() as u64 - 5,
+                    kind: ViolationKind::NonCanonicalFloat
+                }
+            ]
+        );
+        bytes = to_dag_cbor(&bytes).unwrap();
+        assert_eq!(check(&bytes).unwrap(), vec![]);
+        // keys sorted length-first
+        assert_eq!(bytes[1..5], [0x61, b'a', 0x01, 0x61]);
+        assert_eq!(bytes[5], b'c');
+        assert_eq!(RawCborCodec.decode::<Ipld>(&bytes).unwrap(), ipld);
+    }
+
+    #[test]
+    fn convertible() {
+        let bytes = [
+            0xbf, // indefinite length map
+            0x62, b'b', b'b', 0x18, 0x01, // "bb": 1 with non-minimal head
+            0x61, b'a', 0xf9, 0x3c, 0x00, // "a": 1.0 as half float
+            0xff,
+        ];
+        let kinds = check(&bytes)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ViolationKind::IndefiniteLength,
+                ViolationKind::NonMinimalHead,
+                ViolationKind::UnsortedKeys,
+                ViolationKind::NonCanonicalFloat,
+            ]
+        );
+        let bytes = to_dag_cbor(&bytes).unwrap();
+        assert_eq!(
+            bytes,
+            [0xa2, 0x61, b'a', 0xfb, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0, 0x62, b'b', b'b', 0x01]
+        );
+    }
+
+    #[test]
+    fn not_convertible() {
+        let mut map = BTreeMap::new();
+        map.insert(1u8, "x".to_string());
+        let bytes = RawCborCodec.encode(&map).unwrap();
+        assert_eq!(
+            check(&bytes).unwrap(),
+            vec![Violation {
+                offset: 1,
+                kind: ViolationKind::NonStringKey
+            }]
+        );
+        let err = to_dag_cbor(&bytes).unwrap_err();
+        assert_eq!(err.downcast::<NotDagCbor>().unwrap().0.offset, 1);
+
+        let bytes = [0xc1, 0x00];
+        assert_eq!(
+            check(&bytes).unwrap()[0].kind,
+            ViolationKind::UnsupportedTag(1)
+        );
+        assert!(to_dag_cbor(&bytes).is_err());
+        assert!(to_dag_cbor(&[0xf7]).is_err());
+        assert!(to_dag_cbor(&[0xf9, 0x7c, 0x00]).is_err());
+        assert!(check(&[0x82, 0x01]).is_err());
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        // {"a": 1, "b": 2, "a": 3}
+        let bytes = [0xa3, 0x61, b'a', 0x01, 0x61, b'b', 0x02, 0x61, b'a', 0x03];
+        assert_eq!(
+            check(&bytes).unwrap(),
+            vec![Violation {
+                offset: 7,
+                kind: ViolationKind::DuplicateKey
+            }]
+        );
+        let err = to_dag_cbor(&bytes).unwrap_err();
+        assert_eq!(
+            err.downcast::<NotDagCbor>().unwrap().0.kind,
+            ViolationKind::DuplicateKey
+        );
+
+        // the same key with a non-minimal head is still a duplicate
+        let bytes = [0xa2, 0x61, b'a', 0x01, 0x78, 0x01, b'a', 0x02];
+        let kinds = check(&bytes)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![ViolationKind::NonMinimalHead, ViolationKind::DuplicateKey]
+        );
+        assert!(to_dag_cbor(&bytes).is_err());
+    }
+
+    #[test]
+    fn non_minimal_heads() {
+        let kinds = |bytes: &[u8]| {
+            check(bytes)
+                .unwrap()
+                .into_iter()
+                .map(|v| v.kind)
+                .collect::<Vec<_>>()
+        };
+        // {"a": 1, "bb": 2} and {"b": 1, "a": 2} with a non-minimal head for "a"
+        let bytes = [0xa2, 0x78, 0x01, b'a', 0x01, 0x62, b'b', b'b', 0x02];
+        assert_eq!(kinds(&bytes), vec![ViolationKind::NonMinimalHead]);
+        let bytes = [0xa2, 0x61, b'b', 0x01, 0x78, 0x01, b'a', 0x02];
+        assert_eq!(
+            kinds(&bytes),
+            vec![ViolationKind::NonMinimalHead, ViolationKind::UnsortedKeys]
+        );
+
+        // a link with a non-minimal byte string head
+        let cid = Cid::new_v1(0x71, Code::Sha2_256.digest(&b"cid"[..]));
+        let canonical = RawCborCodec.encode(&cid).unwrap();
+        let mut bytes = vec![0xd8, 0x2a, 0x59, 0x00];
+        bytes.extend_from_slice(&canonical[3..]);
+        assert_eq!(
+            check(&bytes).unwrap(),
+            vec![Violation {
+                offset: 2,
+                kind: ViolationKind::NonMinimalHead
+            }]
+        );
+        assert_eq!(to_dag_cbor(&bytes).unwrap(), canonical);
+    }
+}
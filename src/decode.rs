@@ -1,8 +1,18 @@
 //! CBOR decoder
 use crate::{
-    error::{InvalidCidPrefix, LengthOutOfRange, UnexpectedCode, UnexpectedEof, UnknownTag},
+    error::{
+        InvalidCidPrefix, InvalidLink, InvalidStringRef, LengthOutOfRange, Location,
+        NumberOutOfRange, UnexpectedCode, UnexpectedEof, UnknownTag,
+    },
+    io::{Read, Reader, Seek, Slice},
+    options::{self, NumberMode, UndefinedMode},
+    path::PathSegment,
+    self_described::SELF_DESCRIBE,
+    stringref::{self, NAMESPACE, STRINGREF},
+    token::{items, read_payload, read_token, read_token_from, skip_payload, to_usize, Token},
     RawCborCodec,
 };
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use byteorder::{BigEndian, ByteOrder};
 use core::convert::TryFrom;
 use libipld_core::{
@@ -12,81 +22,145 @@ use libipld_core::{
     ipld::Ipld,
     raw_value::SkipOne,
 };
-use std::{
-    collections::BTreeMap,
-    io::{Read, Seek, SeekFrom},
-    sync::Arc,
-};
+
+/// Reads `N` bytes, straight from the slice if `r` is a [`crate::io::SliceReader`].
+#[inline]
+fn read_array<R: Reader + ?Sized, const N: usize>(r: &mut R) -> Result<[u8; N]> {
+    let mut buf = [0; N];
+    match Slice::of(r) {
+        Some(mut slice) => buf.copy_from_slice(slice.take(N)?),
+        None => r.read_exact(&mut buf)?,
+    }
+    Ok(buf)
+}
 
 /// Reads a u8 from a byte stream.
-pub fn read_u8<R: Read + Seek>(r: &mut R) -> Result<u8> {
-    let mut buf = [0; 1];
-    r.read_exact(&mut buf)?;
-    Ok(buf[0])
+pub fn read_u8<R: Reader + ?Sized>(r: &mut R) -> Result<u8> {
+    Ok(read_array::<_, 1>(r)?[0])
 }
 
 /// Reads a u16 from a byte stream.
-pub fn read_u16<R: Read + Seek>(r: &mut R) -> Result<u16> {
-    let mut buf = [0; 2];
-    r.read_exact(&mut buf)?;
-    Ok(BigEndian::read_u16(&buf))
+pub fn read_u16<R: Reader + ?Sized>(r: &mut R) -> Result<u16> {
+    Ok(BigEndian::read_u16(&read_array::<_, 2>(r)?))
 }
 
 /// Reads a u32 from a byte stream.
-pub fn read_u32<R: Read + Seek>(r: &mut R) -> Result<u32> {
-    let mut buf = [0; 4];
-    r.read_exact(&mut buf)?;
-    Ok(BigEndian::read_u32(&buf))
+pub fn read_u32<R: Reader + ?Sized>(r: &mut R) -> Result<u32> {
+    Ok(BigEndian::read_u32(&read_array::<_, 4>(r)?))
 }
 
 /// Reads a u64 from a byte stream.
-pub fn read_u64<R: Read + Seek>(r: &mut R) -> Result<u64> {
-    let mut buf = [0; 8];
-    r.read_exact(&mut buf)?;
-    Ok(BigEndian::read_u64(&buf))
+pub fn read_u64<R: Reader + ?Sized>(r: &mut R) -> Result<u64> {
+    Ok(BigEndian::read_u64(&read_array::<_, 8>(r)?))
 }
 
 /// Reads a f32 from a byte stream.
-pub fn read_f32<R: Read + Seek>(r: &mut R) -> Result<f32> {
-    let mut buf = [0; 4];
-    r.read_exact(&mut buf)?;
-    Ok(BigEndian::read_f32(&buf))
+pub fn read_f32<R: Reader + ?Sized>(r: &mut R) -> Result<f32> {
+    Ok(BigEndian::read_f32(&read_array::<_, 4>(r)?))
 }
 
 /// Reads a f64 from a byte stream.
-pub fn read_f64<R: Read + Seek>(r: &mut R) -> Result<f64> {
-    let mut buf = [0; 8];
-    r.read_exact(&mut buf)?;
-    Ok(BigEndian::read_f64(&buf))
+pub fn read_f64<R: Reader + ?Sized>(r: &mut R) -> Result<f64> {
+    Ok(BigEndian::read_f64(&read_array::<_, 8>(r)?))
 }
 
 /// Reads `len` number of bytes from a byte stream.
-pub fn read_bytes<R: Read + Seek>(r: &mut R, len: usize) -> Result<Vec<u8>> {
+pub fn read_bytes<R: Reader + ?Sized>(r: &mut R, len: usize) -> Result<Vec<u8>> {
+    if let Some(mut slice) = Slice::of(r) {
+        return Ok(slice.take(len)?.to_vec());
+    }
     // Limit up-front allocations to 16KiB as the length is user controlled.
     let mut buf = Vec::with_capacity(len.min(16 * 1024));
-    r.take(len as u64).read_to_end(&mut buf)?;
-    if buf.len() != len {
-        return Err(UnexpectedEof.into());
+    while buf.len() < len {
+        let start = buf.len();
+        buf.resize(start + (len - start).min(16 * 1024), 0);
+        r.read_exact(&mut buf[start..])?;
     }
     Ok(buf)
 }
 
 /// Reads `len` number of bytes from a byte stream and converts them to a string.
-pub fn read_str<R: Read + Seek>(r: &mut R, len: usize) -> Result<String> {
+pub fn read_str<R: Reader + ?Sized>(r: &mut R, len: usize) -> Result<String> {
     let bytes = read_bytes(r, len)?;
     Ok(String::from_utf8(bytes)?)
 }
 
+/// Skips `len` bytes, failing with [`UnexpectedEof`] if the input ends first.
+///
+/// Seeking past the end of the input succeeds, so the last byte is read to check that it exists.
+pub(crate) fn skip_bytes<R: Reader + ?Sized>(r: &mut R, len: u64) -> Result<()> {
+    if len == 0 {
+        return Ok(());
+    }
+    r.advance(i64::try_from(len - 1).map_err(|_| UnexpectedEof)?)?;
+    read_u8(r)?;
+    Ok(())
+}
+
+/// The read position, without seeking if `r` is a [`crate::io::SliceReader`].
+#[inline]
+fn position<R: Reader + ?Sized>(r: &mut R) -> Result<u64> {
+    match Slice::of(r) {
+        Some(slice) => Ok(slice.position()),
+        None => r.position(),
+    }
+}
+
+/// Reads the element at `index` of an array with `f`, attaching its location to errors.
+pub(crate) fn element<R: Reader + ?Sized, T>(
+    r: &mut R,
+    index: usize,
+    f: impl FnOnce(&mut R) -> Result<T>,
+) -> Result<T> {
+    let offset = position(r)?;
+    f(r).map_err(|err| Location::prepend(Location::attach(err, offset), PathSegment::Index(index)))
+}
+
+/// Reads a map key with `f`, attaching its location to errors.
+///
+/// Returns the key together with its offset, which is needed to locate errors in the value.
+pub(crate) fn map_key<R: Reader + ?Sized, K>(
+    r: &mut R,
+    f: impl FnOnce(&mut R) -> Result<K>,
+) -> Result<(K, u64)> {
+    let offset = position(r)?;
+    let key = f(r).map_err(|err| Location::attach(err, offset))?;
+    Ok((key, offset))
+}
+
+/// Reads the value of the map key at `key_offset` with `f`, attaching its location to errors.
+pub(crate) fn map_value<R: Reader + ?Sized, T>(
+    r: &mut R,
+    key_offset: u64,
+    f: impl FnOnce(&mut R) -> Result<T>,
+) -> Result<T> {
+    let offset = position(r)?;
+    f(r).map_err(|err| {
+        let err = Location::attach(err, offset);
+        match read_key(r, key_offset, offset) {
+            Ok(key) => Location::prepend(err, PathSegment::Key(key)),
+            Err(_) => err,
+        }
+    })
+}
+
+/// Reads the encoded key between `start` and `end` again after its value failed to decode.
+fn read_key<R: Reader + ?Sized>(r: &mut R, start: u64, end: u64) -> Result<Box<[u8]>> {
+    let position = r.position()?;
+    r.advance(start as i64 - position as i64)?;
+    Ok(read_bytes(r, (end - start) as usize)?.into_boxed_slice())
+}
+
 /// Reads a list of any type that implements `TryReadCbor` from a stream of cbor encoded bytes.
 pub fn read_list<R: Read + Seek, T: Decode<RawCborCodec>>(r: &mut R, len: usize) -> Result<Vec<T>> {
     // Limit up-front allocations to 16KiB as the length is user controlled.
     //
     // Can't make this "const" because the generic, but it _should_ be known at compile time.
-    let max_alloc = (16 * 1024) / std::mem::size_of::<T>();
+    let max_alloc = (16 * 1024) / core::mem::size_of::<T>();
 
     let mut list: Vec<T> = Vec::with_capacity(len.min(max_alloc));
-    for _ in 0..len {
-        list.push(T::decode(RawCborCodec, r)?);
+    for i in 0..len {
+        list.push(element(r, i, |r| T::decode(RawCborCodec, r))?);
     }
     Ok(list)
 }
@@ -99,8 +173,8 @@ pub fn read_list_il<R: Read + Seek, T: Decode<RawCborCodec>>(r: &mut R) -> Resul
         if major == 0xff {
             break;
         }
-        r.seek(SeekFrom::Current(-1))?;
-        let value = T::decode(RawCborCodec, r)?;
+        r.advance(-1)?;
+        let value = element(r, list.len(), |r| T::decode(RawCborCodec, r))?;
         list.push(value);
     }
     Ok(list)
@@ -113,8 +187,8 @@ pub fn read_map<R: Read + Seek, K: Decode<RawCborCodec> + Ord, T: Decode<RawCbor
 ) -> Result<BTreeMap<K, T>> {
     let mut map: BTreeMap<K, T> = BTreeMap::new();
     for _ in 0..len {
-        let key = K::decode(RawCborCodec, r)?;
-        let value = T::decode(RawCborCodec, r)?;
+        let (key, offset) = map_key(r, |r| K::decode(RawCborCodec, r))?;
+        let value = map_value(r, offset, |r| T::decode(RawCborCodec, r))?;
         map.insert(key, value);
     }
     Ok(map)
@@ -130,36 +204,105 @@ pub fn read_map_il<R: Read + Seek, K: Decode<RawCborCodec> + Ord, T: Decode<RawC
         if major == 0xff {
             break;
         }
-        r.seek(SeekFrom::Current(-1))?;
-        let key = K::decode(RawCborCodec, r)?;
-        let value = T::decode(RawCborCodec, r)?;
+        r.advance(-1)?;
+        let (key, offset) = map_key(r, |r| K::decode(RawCborCodec, r))?;
+        let value = map_value(r, offset, |r| T::decode(RawCborCodec, r))?;
         map.insert(key, value);
     }
     Ok(map)
 }
 
 /// Reads a cid from a stream of cbor encoded bytes.
-pub fn read_link<R: Read + Seek>(r: &mut R) -> Result<Cid> {
-    let ty = read_u8(r)?;
-    if ty != 0x58 {
-        return Err(UnknownTag(ty).into());
-    }
-    let len = read_u8(r)?;
+///
+/// Expects the item following tag 42, which must be a byte string holding the cid with a zero
+/// prefix byte.
+pub fn read_link<R: Reader + ?Sized>(r: &mut R) -> Result<Cid> {
+    let major = read_u8(r)?;
+    if !(0x40..=0x5b).contains(&major) {
+        return Err(InvalidLink(major).into());
+    }
+    let len = read_len(r, major - 0x40)?;
     if len == 0 {
         return Err(LengthOutOfRange::new::<Cid>().into());
     }
-    let bytes = read_bytes(r, len as usize)?;
+    match Slice::of(r) {
+        Some(mut slice) => cid_from_bytes(slice.take(len)?),
+        None => cid_from_bytes(&read_bytes(r, len)?),
+    }
+}
+
+/// Converts the payload of a link to a cid.
+fn cid_from_bytes(bytes: &[u8]) -> Result<Cid> {
     if bytes[0] != 0 {
         return Err(InvalidCidPrefix(bytes[0]).into());
     }
 
     // skip the first byte per
     // https://github.com/ipld/specs/blob/master/block-layer/codecs/dag-cbor.md#links
-    Ok(Cid::try_from(&bytes[1..])?)
+    #[cfg(feature = "std")]
+    let cid = Cid::try_from(&bytes[1..])?;
+    // without std, cid errors don't implement `Error`
+    #[cfg(not(feature = "std"))]
+    let cid = Cid::try_from(&bytes[1..]).map_err(libipld_core::error::Error::msg)?;
+    Ok(cid)
+}
+
+/// Reads the head of an item into `buf`.
+///
+/// Returns the length of the head and its argument, which is `None` for indefinite length items
+/// and the break code. For floats the argument is the raw bit pattern.
+pub(crate) fn read_head<R: Reader + ?Sized>(
+    r: &mut R,
+    buf: &mut [u8; 9],
+) -> Result<(usize, Option<u64>)> {
+    let major = read_u8(r)?;
+    buf[0] = major;
+    let info = major & 0x1f;
+    let len = match info {
+        0x00..=0x17 => return Ok((1, Some(info as u64))),
+        0x18 => 1,
+        0x19 => 2,
+        0x1a => 4,
+        0x1b => 8,
+        0x1f if matches!(major >> 5, 2..=5 | 7) => return Ok((1, None)),
+        _ => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+    };
+    match Slice::of(r) {
+        Some(mut slice) => buf[1..=len].copy_from_slice(slice.take(len)?),
+        None => r.read_exact(&mut buf[1..=len])?,
+    }
+    Ok((len + 1, Some(BigEndian::read_uint(&buf[1..=len], len))))
+}
+
+/// Converts the bits of a half precision float to a f64.
+pub(crate) fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (bits >> 10) & 0x1f;
+    let mant = f64::from(bits & 0x3ff);
+    let value = match exp {
+        0 => mant / f64::from(1u32 << 24),
+        31 if mant == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        // 2^(exp - 25), built from its bits since `powi` needs std
+        _ => (mant + 1024.0) * f64::from_bits((u64::from(exp) + 1023 - 25) << 52),
+    };
+    sign * value
+}
+
+/// Reads a tag number given the additional information of the tag head.
+pub fn read_tag<R: Reader + ?Sized>(r: &mut R, info: u8) -> Result<u64> {
+    Ok(match info {
+        0x00..=0x17 => info as u64,
+        0x18 => read_u8(r)? as u64,
+        0x19 => read_u16(r)? as u64,
+        0x1a => read_u32(r)? as u64,
+        0x1b => read_u64(r)?,
+        info => return Err(UnexpectedCode::new::<u64>(info).into()),
+    })
 }
 
 /// Reads the len given a base.
-pub fn read_len<R: Read + Seek>(r: &mut R, major: u8) -> Result<usize> {
+pub fn read_len<R: Reader + ?Sized>(r: &mut R, major: u8) -> Result<usize> {
     Ok(match major {
         0x00..=0x17 => major as usize,
         0x18 => read_u8(r)? as usize,
@@ -167,7 +310,7 @@ pub fn read_len<R: Read + Seek>(r: &mut R, major: u8) -> Result<usize> {
         0x1a => read_u32(r)? as usize,
         0x1b => {
             let len = read_u64(r)?;
-            if len > usize::max_value() as u64 {
+            if len > usize::MAX as u64 {
                 return Err(LengthOutOfRange::new::<usize>().into());
             }
             len as usize
@@ -176,225 +319,255 @@ pub fn read_len<R: Read + Seek>(r: &mut R, major: u8) -> Result<usize> {
     })
 }
 
+/// Narrows the argument of an integer token, failing with [`NumberOutOfRange`] for `T`.
+fn narrow<T, U: TryFrom<u64>>(n: u64) -> Result<U> {
+    U::try_from(n).map_err(|_| NumberOutOfRange::new::<T>().into())
+}
+
+/// Reads the token of a number decoded as `T`, together with the current [`NumberMode`].
+///
+/// Outside of [`NumberMode::Lenient`], fails with [`UnexpectedCode`] if the additional
+/// information of the head is larger than `max`, i.e. the head is wider than `T`. In
+/// [`NumberMode::Strict`], also fails if the head is longer than needed.
+fn read_number<T, R: Reader + ?Sized>(r: &mut R, max: u8) -> Result<(u8, Token, NumberMode)> {
+    let major = read_u8(r)?;
+    let mode = options::current().number_mode();
+    if mode != NumberMode::Lenient && major & 0x1f > max {
+        return Err(UnexpectedCode::new::<T>(major).into());
+    }
+    let token = read_token_from(r, major)?;
+    if mode == NumberMode::Strict && !is_minimal(major, token) {
+        return Err(UnexpectedCode::new::<T>(major).into());
+    }
+    Ok((major, token, mode))
+}
+
+/// Whether a number is encoded with the head the encoder would have written for it.
+#[allow(clippy::float_cmp)]
+fn is_minimal(major: u8, token: Token) -> bool {
+    let info = major & 0x1f;
+    match token {
+        Token::UInt(n) | Token::NInt(n) => {
+            info == match n {
+                0..=0x17 => n as u8,
+                0x18..=0xff => 0x18,
+                0x100..=0xffff => 0x19,
+                0x1_0000..=0xffff_ffff => 0x1a,
+                _ => 0x1b,
+            }
+        }
+        Token::Float(x) => match info {
+            0x19 => true,
+            0x1a => x.is_finite(),
+            _ => x.is_finite() && f64::from(x as f32) != x,
+        },
+        _ => true,
+    }
+}
+
+/// Converts a number token to the integer type `T` in [`NumberMode::Lenient`].
+///
+/// Floats are accepted if they have an integral value, and all values must be in range.
+#[allow(clippy::float_cmp)]
+fn coerce_integer<T: TryFrom<i128>>(major: u8, token: Token) -> Result<T> {
+    let n = match token {
+        Token::UInt(n) => i128::from(n),
+        Token::NInt(n) => -1 - i128::from(n),
+        // the range check keeps the cast from saturating
+        Token::Float(x) if (-1e38..1e38).contains(&x) && x as i128 as f64 == x => x as i128,
+        _ => return Err(UnexpectedCode::new::<T>(major).into()),
+    };
+    T::try_from(n).map_err(|_| NumberOutOfRange::new::<T>().into())
+}
+
+/// Converts an integer token to a float in [`NumberMode::Lenient`], if it fits without loss.
+fn coerce_float<T>(major: u8, token: Token) -> Result<f64> {
+    let n = match token {
+        Token::UInt(n) => i128::from(n),
+        Token::NInt(n) => -1 - i128::from(n),
+        _ => return Err(UnexpectedCode::new::<T>(major).into()),
+    };
+    let x = n as f64;
+    if x as i128 != n {
+        return Err(NumberOutOfRange::new::<T>().into());
+    }
+    Ok(x)
+}
+
 impl Decode<RawCborCodec> for bool {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0xf4 => false,
-            0xf5 => true,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::Simple(20) => Ok(false),
+            Token::Simple(21) => Ok(true),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for u8 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x00..=0x17 => major,
-            0x18 => read_u8(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x18)?;
+        match (token, mode) {
+            (Token::UInt(n), _) => narrow::<Self, _>(n),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for u16 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x00..=0x17 => major as u16,
-            0x18 => read_u8(r)? as u16,
-            0x19 => read_u16(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x19)?;
+        match (token, mode) {
+            (Token::UInt(n), _) => narrow::<Self, _>(n),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for u32 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x00..=0x17 => major as u32,
-            0x18 => read_u8(r)? as u32,
-            0x19 => read_u16(r)? as u32,
-            0x1a => read_u32(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x1a)?;
+        match (token, mode) {
+            (Token::UInt(n), _) => narrow::<Self, _>(n),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for u64 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x00..=0x17 => major as u64,
-            0x18 => read_u8(r)? as u64,
-            0x19 => read_u16(r)? as u64,
-            0x1a => read_u32(r)? as u64,
-            0x1b => read_u64(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x1b)?;
+        match (token, mode) {
+            (Token::UInt(n), _) => Ok(n),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for i8 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x20..=0x37 => -1 - (major - 0x20) as i8,
-            0x38 => -1 - read_u8(r)? as i8,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x18)?;
+        match (token, mode) {
+            (Token::NInt(n), _) => Ok(-1 - narrow::<Self, i8>(n)?),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for i16 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x20..=0x37 => -1 - (major - 0x20) as i16,
-            0x38 => -1 - read_u8(r)? as i16,
-            0x39 => -1 - read_u16(r)? as i16,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x19)?;
+        match (token, mode) {
+            (Token::NInt(n), _) => Ok(-1 - narrow::<Self, i16>(n)?),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for i32 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x20..=0x37 => -1 - (major - 0x20) as i32,
-            0x38 => -1 - read_u8(r)? as i32,
-            0x39 => -1 - read_u16(r)? as i32,
-            0x3a => -1 - read_u32(r)? as i32,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x1a)?;
+        match (token, mode) {
+            (Token::NInt(n), _) => Ok(-1 - narrow::<Self, i32>(n)?),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for i64 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0x20..=0x37 => -1 - (major - 0x20) as i64,
-            0x38 => -1 - read_u8(r)? as i64,
-            0x39 => -1 - read_u16(r)? as i64,
-            0x3a => -1 - read_u32(r)? as i64,
-            0x3b => -1 - read_u64(r)? as i64,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x1b)?;
+        match (token, mode) {
+            (Token::NInt(n), _) => Ok(-1 - narrow::<Self, i64>(n)?),
+            (_, NumberMode::Lenient) => coerce_integer(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for f32 {
+    #[allow(clippy::float_cmp)]
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0xfa => read_f32(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
+        let (major, token, mode) = read_number::<Self, _>(r, 0x1a)?;
+        match (token, mode) {
+            // the encoder writes infinity and NaN as half floats
+            (Token::Float(x), _) if major == 0xfa || !x.is_finite() => Ok(x as f32),
+            // only other floats that fit without losing precision
+            (Token::Float(x), NumberMode::Lenient) if f64::from(x as f32) == x || x.is_nan() => {
+                Ok(x as f32)
+            }
+            (Token::UInt(_) | Token::NInt(_), NumberMode::Lenient) => {
+                let x = coerce_float::<Self>(major, token)?;
+                if f64::from(x as f32) != x {
+                    return Err(NumberOutOfRange::new::<Self>().into());
+                }
+                Ok(x as f32)
             }
-        };
-        Ok(result)
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for f64 {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
-        let major = read_u8(r)?;
-        let result = match major {
-            0xfa => read_f32(r)? as f64,
-            0xfb => read_f64(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        let (major, token, mode) = read_number::<Self, _>(r, 0x1b)?;
+        match (token, mode) {
+            // the encoder writes infinity and NaN as half floats
+            (Token::Float(x), _) if major != 0xf9 || !x.is_finite() => Ok(x),
+            (Token::Float(x), NumberMode::Lenient) => Ok(x),
+            (_, NumberMode::Lenient) => coerce_float::<Self>(major, token),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for String {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x60..=0x7b => {
-                let len = read_len(r, major - 0x60)?;
-                read_str(r, len)?
-            }
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            token @ Token::Text(_) => Ok(String::from_utf8(read_payload(r, token)?)?),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl Decode<RawCborCodec> for Cid {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        if major == 0xd8 {
-            if let Ok(tag) = read_u8(r) {
-                if tag == 42 {
-                    return read_link(r);
-                }
-            }
+        match read_token_from(r, major)? {
+            Token::Tag(42) => read_link(r),
+            Token::Tag(tag) => Err(UnknownTag(tag).into()),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
         }
-        Err(UnexpectedCode::new::<Self>(major).into())
     }
 }
 
 impl Decode<RawCborCodec> for Box<[u8]> {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x40..=0x5b => {
-                let len = read_len(r, major - 0x40)?;
-                read_bytes(r, len)?.into_boxed_slice()
-            }
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            token @ Token::Bytes(_) => Ok(read_payload(r, token)?.into_boxed_slice()),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl<T: Decode<RawCborCodec>> Decode<RawCborCodec> for Option<T> {
     fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0xf6 => None,
-            0xf7 => None,
+        let result = match (major, options::current().undefined_mode()) {
+            (0xf6, _) | (0xf7, UndefinedMode::Null) => None,
+            (0xf7, UndefinedMode::Error) => return Err(UnexpectedCode::new::<Self>(major).into()),
             _ => {
-                r.seek(SeekFrom::Current(-1))?;
+                r.advance(-1)?;
                 Some(T::decode(c, r)?)
             }
         };
@@ -405,110 +578,56 @@ impl<T: Decode<RawCborCodec>> Decode<RawCborCodec> for Option<T> {
 impl<T: Decode<RawCborCodec>> Decode<RawCborCodec> for Vec<T> {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x80..=0x9b => {
-                let len = read_len(r, major - 0x80)?;
-                read_list(r, len)?
-            }
-            0x9f => read_list_il(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::ArrayStart(Some(len)) => read_list(r, to_usize(len)?),
+            Token::ArrayStart(None) => read_list_il(r),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl<K: Decode<RawCborCodec> + Ord, T: Decode<RawCborCodec>> Decode<RawCborCodec> for BTreeMap<K, T> {
     fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0xa0..=0xbb => {
-                let len = read_len(r, major - 0xa0)?;
-                read_map(r, len)?
-            }
-            0xbf => read_map_il(r)?,
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::MapStart(Some(len)) => read_map(r, to_usize(len)?),
+            Token::MapStart(None) => read_map_il(r),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 /// Note that since CBOR is a superset of IPLD, this is not guaranteed to succeed for arbitrary CBOR.
+///
+/// The self-describe tag 55799 is skipped wherever it occurs.
 impl Decode<RawCborCodec> for Ipld {
-    fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
+    fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let ipld = match major {
-            // Major type 0: an unsigned integer
-            0x00..=0x17 => Self::Integer(major as i128),
-            0x18 => Self::Integer(read_u8(r)? as i128),
-            0x19 => Self::Integer(read_u16(r)? as i128),
-            0x1a => Self::Integer(read_u32(r)? as i128),
-            0x1b => Self::Integer(read_u64(r)? as i128),
-
-            // Major type 1: a negative integer
-            0x20..=0x37 => Self::Integer(-1 - (major - 0x20) as i128),
-            0x38 => Self::Integer(-1 - read_u8(r)? as i128),
-            0x39 => Self::Integer(-1 - read_u16(r)? as i128),
-            0x3a => Self::Integer(-1 - read_u32(r)? as i128),
-            0x3b => Self::Integer(-1 - read_u64(r)? as i128),
-
-            // Major type 2: a byte string
-            0x40..=0x5b => {
-                let len = read_len(r, major - 0x40)?;
-                let bytes = read_bytes(r, len as usize)?;
-                Self::Bytes(bytes)
-            }
-
-            // Major type 3: a text string
-            0x60..=0x7b => {
-                let len = read_len(r, major - 0x60)?;
-                let string = read_str(r, len as usize)?;
-                Self::String(string)
-            }
-
-            // Major type 4: an array of data items
-            0x80..=0x9b => {
-                let len = read_len(r, major - 0x80)?;
-                let list = read_list(r, len as usize)?;
-                Self::List(list)
-            }
-
-            // Major type 4: an array of data items (indefinite length)
-            0x9f => {
-                let list = read_list_il(r)?;
-                Self::List(list)
-            }
-
-            // Major type 5: a map of pairs of data items
-            0xa0..=0xbb => {
-                let len = read_len(r, major - 0xa0)?;
-                Self::Map(read_map(r, len as usize)?)
-            }
-
-            // Major type 5: a map of pairs of data items (indefinite length)
-            0xbf => Self::Map(read_map_il(r)?),
-
-            // Major type 6: optional semantic tagging of other major types
-            0xd8 => {
-                let tag = read_u8(r)?;
-                if tag == 42 {
-                    Self::Link(read_link(r)?)
-                } else {
-                    return Err(UnknownTag(tag).into());
-                }
+        let ipld = match read_token_from(r, major)? {
+            Token::UInt(n) => Self::Integer(n as i128),
+            Token::NInt(n) => Self::Integer(-1 - n as i128),
+            token @ Token::Bytes(_) => Self::Bytes(read_payload(r, token)?),
+            token @ Token::Text(_) => Self::String(String::from_utf8(read_payload(r, token)?)?),
+            Token::ArrayStart(Some(len)) => Self::List(read_list(r, to_usize(len)?)?),
+            Token::ArrayStart(None) => Self::List(read_list_il(r)?),
+            Token::MapStart(Some(len)) => Self::Map(read_map(r, to_usize(len)?)?),
+            Token::MapStart(None) => Self::Map(read_map_il(r)?),
+            Token::Tag(42) => Self::Link(read_link(r)?),
+            Token::Tag(SELF_DESCRIBE) => Self::decode(c, r)?,
+            Token::Tag(NAMESPACE) => stringref::decode_namespace(c, r)?,
+            // references are resolved while reading their namespace
+            Token::Tag(STRINGREF) => return Err(InvalidStringRef(u64::decode(c, r)?).into()),
+            Token::Tag(tag) => return Err(UnknownTag(tag).into()),
+            Token::Simple(20) => Self::Bool(false),
+            Token::Simple(21) => Self::Bool(true),
+            Token::Simple(22) => Self::Null,
+            Token::Simple(23) if options::current().undefined_mode() == UndefinedMode::Null => {
+                Self::Null
+            }
+            Token::Float(x) => Self::Float(x),
+            Token::Simple(_) | Token::Break => {
+                return Err(UnexpectedCode::new::<Self>(major).into());
             }
-
-            // Major type 7: floating-point numbers and other simple data types that need no content
-            0xf4 => Self::Bool(false),
-            0xf5 => Self::Bool(true),
-            0xf6 => Self::Null,
-            0xf7 => Self::Null,
-            0xfa => Self::Float(read_f32(r)? as f64),
-            0xfb => Self::Float(read_f64(r)?),
-            _ => return Err(UnexpectedCode::new::<Self>(major).into()),
         };
         Ok(ipld)
     }
@@ -520,118 +639,39 @@ impl References<RawCborCodec> for Ipld {
         r: &mut R,
         set: &mut E,
     ) -> Result<()> {
-        let major = read_u8(r)?;
-        match major {
-            // Major type 0: an unsigned integer
-            0x00..=0x17 => {}
-            0x18 => {
-                r.seek(SeekFrom::Current(1))?;
-            }
-            0x19 => {
-                r.seek(SeekFrom::Current(2))?;
-            }
-            0x1a => {
-                r.seek(SeekFrom::Current(4))?;
-            }
-            0x1b => {
-                r.seek(SeekFrom::Current(8))?;
-            }
-
-            // Major type 1: a negative integer
-            0x20..=0x37 => {}
-            0x38 => {
-                r.seek(SeekFrom::Current(1))?;
-            }
-            0x39 => {
-                r.seek(SeekFrom::Current(2))?;
-            }
-            0x3a => {
-                r.seek(SeekFrom::Current(4))?;
-            }
-            0x3b => {
-                r.seek(SeekFrom::Current(8))?;
-            }
-
-            // Major type 2: a byte string
-            0x40..=0x5b => {
-                let len = read_len(r, major - 0x40)?;
-                r.seek(SeekFrom::Current(len as _))?;
-            }
-
-            // Major type 3: a text string
-            0x60..=0x7b => {
-                let len = read_len(r, major - 0x60)?;
-                r.seek(SeekFrom::Current(len as _))?;
-            }
-
-            // Major type 4: an array of data items
-            0x80..=0x9b => {
-                let len = read_len(r, major - 0x80)?;
-                for _ in 0..len {
-                    <Self as References<RawCborCodec>>::references(c, r, set)?;
-                }
-            }
-
-            // Major type 4: an array of data items (indefinite length)
-            0x9f => loop {
-                let major = read_u8(r)?;
-                if major == 0xff {
-                    break;
-                }
-                r.seek(SeekFrom::Current(-1))?;
-                <Self as References<RawCborCodec>>::references(c, r, set)?;
-            },
-
-            // Major type 5: a map of pairs of data items
-            0xa0..=0xbb => {
-                let len = read_len(r, major - 0xa0)?;
-                for _ in 0..len {
-                    <Self as References<RawCborCodec>>::references(c, r, set)?;
-                    <Self as References<RawCborCodec>>::references(c, r, set)?;
-                }
-            }
-
-            // Major type 5: a map of pairs of data items (indefinite length)
-            0xbf => loop {
-                let major = read_u8(r)?;
-                if major == 0xff {
-                    break;
-                }
-                r.seek(SeekFrom::Current(-1))?;
-                <Self as References<RawCborCodec>>::references(c, r, set)?;
-                <Self as References<RawCborCodec>>::references(c, r, set)?;
-            },
-
-            // Major type 6: optional semantic tagging of other major types
-            0xd8 => {
-                let tag = read_u8(r)?;
-                if tag == 42 {
-                    set.extend(std::iter::once(read_link(r)?));
-                } else {
-                    <Self as References<RawCborCodec>>::references(c, r, set)?;
-                }
-            }
-
-            // Major type 7: floating-point numbers and other simple data types that need no content
-            0xf4..=0xf7 => {}
-            0xf8 => {
-                r.seek(SeekFrom::Current(1))?;
-            }
-            0xf9 => {
-                r.seek(SeekFrom::Current(2))?;
-            }
-            0xfa => {
-                r.seek(SeekFrom::Current(4))?;
-            }
-            0xfb => {
-                r.seek(SeekFrom::Current(8))?;
-            }
-            major => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+        match read_token(r)? {
+            Token::UInt(_) | Token::NInt(_) | Token::Simple(_) | Token::Float(_) => {}
+            token @ (Token::Bytes(_) | Token::Text(_)) => skip_payload(r, token)?,
+            Token::ArrayStart(len) => items(r, len, |r, i| {
+                element(r, i, |r| {
+                    <Self as References<RawCborCodec>>::references(c, r, set)
+                })
+            })?,
+            Token::MapStart(len) => items(r, len, |r, _| {
+                let (_, offset) = map_key(r, |r| {
+                    <Self as References<RawCborCodec>>::references(c, r, set)
+                })?;
+                map_value(r, offset, |r| {
+                    <Self as References<RawCborCodec>>::references(c, r, set)
+                })
+            })?,
+            Token::Tag(42) => set.extend(core::iter::once(read_link(r)?)),
+            Token::Tag(_) => <Self as References<RawCborCodec>>::references(c, r, set)?,
+            Token::Break => return Err(UnexpectedCode::new::<Ipld>(0xff).into()),
         };
         Ok(())
     }
 }
 
+/// Within [`crate::sharing::decode`], references to shared values return the same `Arc`.
+#[cfg(feature = "std")]
+impl<T: Decode<RawCborCodec> + 'static> Decode<RawCborCodec> for Arc<T> {
+    fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
+        crate::sharing::decode_arc(c, r)
+    }
+}
+
+#[cfg(not(feature = "std"))]
 impl<T: Decode<RawCborCodec>> Decode<RawCborCodec> for Arc<T> {
     fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         Ok(Arc::new(T::decode(c, r)?))
@@ -641,39 +681,33 @@ impl<T: Decode<RawCborCodec>> Decode<RawCborCodec> for Arc<T> {
 impl Decode<RawCborCodec> for () {
     fn decode<R: Read + Seek>(_c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        match major {
-            0x80 => {}
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(())
+        match read_token_from(r, major)? {
+            Token::ArrayStart(Some(0)) => Ok(()),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl<A: Decode<RawCborCodec>> Decode<RawCborCodec> for (A,) {
     fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x81 => (A::decode(c, r)?,),
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::ArrayStart(Some(1)) => Ok((element(r, 0, |r| A::decode(c, r))?,)),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl<A: Decode<RawCborCodec>, B: Decode<RawCborCodec>> Decode<RawCborCodec> for (A, B) {
     fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x82 => (A::decode(c, r)?, B::decode(c, r)?),
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::ArrayStart(Some(2)) => Ok((
+                element(r, 0, |r| A::decode(c, r))?,
+                element(r, 1, |r| B::decode(c, r))?,
+            )),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
@@ -682,13 +716,14 @@ impl<A: Decode<RawCborCodec>, B: Decode<RawCborCodec>, C: Decode<RawCborCodec>>
 {
     fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x83 => (A::decode(c, r)?, B::decode(c, r)?, C::decode(c, r)?),
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::ArrayStart(Some(3)) => Ok((
+                element(r, 0, |r| A::decode(c, r))?,
+                element(r, 1, |r| B::decode(c, r))?,
+                element(r, 2, |r| C::decode(c, r))?,
+            )),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
@@ -697,143 +732,30 @@ impl<A: Decode<RawCborCodec>, B: Decode<RawCborCodec>, C: Decode<RawCborCodec>,
 {
     fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
         let major = read_u8(r)?;
-        let result = match major {
-            0x84 => (
-                A::decode(c, r)?,
-                B::decode(c, r)?,
-                C::decode(c, r)?,
-                D::decode(c, r)?,
-            ),
-            _ => {
-                return Err(UnexpectedCode::new::<Self>(major).into());
-            }
-        };
-        Ok(result)
+        match read_token_from(r, major)? {
+            Token::ArrayStart(Some(4)) => Ok((
+                element(r, 0, |r| A::decode(c, r))?,
+                element(r, 1, |r| B::decode(c, r))?,
+                element(r, 2, |r| C::decode(c, r))?,
+                element(r, 3, |r| D::decode(c, r))?,
+            )),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
     }
 }
 
 impl SkipOne for RawCborCodec {
     fn skip<R: Read + Seek>(&self, r: &mut R) -> Result<()> {
-        let major = read_u8(r)?;
-        match major {
-            // Major type 0: an unsigned integer
-            0x00..=0x17 => {}
-            0x18 => {
-                r.seek(SeekFrom::Current(1))?;
-            }
-            0x19 => {
-                r.seek(SeekFrom::Current(2))?;
-            }
-            0x1a => {
-                r.seek(SeekFrom::Current(4))?;
-            }
-            0x1b => {
-                r.seek(SeekFrom::Current(8))?;
-            }
-
-            // Major type 1: a negative integer
-            0x20..=0x37 => {}
-            0x38 => {
-                r.seek(SeekFrom::Current(1))?;
-            }
-            0x39 => {
-                r.seek(SeekFrom::Current(2))?;
-            }
-            0x3a => {
-                r.seek(SeekFrom::Current(4))?;
-            }
-            0x3b => {
-                r.seek(SeekFrom::Current(8))?;
-            }
-
-            // Major type 2: a byte string
-            0x40..=0x5b => {
-                let len = read_len(r, major - 0x40)?;
-                r.seek(SeekFrom::Current(len as _))?;
-            }
-
-            // Major type 3: a text string
-            0x60..=0x7b => {
-                let len = read_len(r, major - 0x60)?;
-                r.seek(SeekFrom::Current(len as _))?;
-            }
-
-            // Major type 4: an array of data items
-            0x80..=0x9b => {
-                let len = read_len(r, major - 0x80)?;
-                for _ in 0..len {
-                    self.skip(r)?;
-                }
-            }
-
-            // Major type 4: an array of data items (indefinite length)
-            0x9f => loop {
-                let major = read_u8(r)?;
-                if major == 0xff {
-                    break;
-                }
-                r.seek(SeekFrom::Current(-1))?;
-                self.skip(r)?;
-            },
-
-            // Major type 5: a map of pairs of data items
-            0xa0..=0xbb => {
-                let len = read_len(r, major - 0xa0)?;
-                for _ in 0..len {
-                    self.skip(r)?;
-                    self.skip(r)?;
-                }
-            }
-
-            // Major type 5: a map of pairs of data items (indefinite length)
-            0xbf => loop {
-                let major = read_u8(r)?;
-                if major == 0xff {
-                    break;
-                }
-                r.seek(SeekFrom::Current(-1))?;
-                self.skip(r)?;
-                self.skip(r)?;
-            },
-
-            // Major type 6: optional semantic tagging of other major types
-            0xc0..=0xd7 => {
-                // let _tag = major - 0xc0;
-                self.skip(r)?;
-            }
-            0xd8 => {
-                r.seek(SeekFrom::Current(1))?;
-                self.skip(r)?;
-            }
-
-            0xd9 => {
-                r.seek(SeekFrom::Current(2))?;
-                self.skip(r)?;
-            }
-            0xda => {
-                r.seek(SeekFrom::Current(4))?;
-                self.skip(r)?;
-            }
-            0xdb => {
-                r.seek(SeekFrom::Current(8))?;
-                self.skip(r)?;
-            }
-
-            // Major type 7: floating-point numbers and other simple data types that need no content
-            0xf4..=0xf7 => {}
-            0xf8 => {
-                r.seek(SeekFrom::Current(1))?;
-            }
-            0xf9 => {
-                r.seek(SeekFrom::Current(2))?;
-            }
-            0xfa => {
-                r.seek(SeekFrom::Current(4))?;
-            }
-            0xfb => {
-                r.seek(SeekFrom::Current(8))?;
-            }
-            major => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+        match read_token(r)? {
+            Token::UInt(_) | Token::NInt(_) | Token::Simple(_) | Token::Float(_) => {}
+            token @ (Token::Bytes(_) | Token::Text(_)) => skip_payload(r, token)?,
+            Token::ArrayStart(len) => items(r, len, |r, i| element(r, i, |r| self.skip(r)))?,
+            Token::MapStart(len) => items(r, len, |r, _| {
+                let (_, offset) = map_key(r, |r| self.skip(r))?;
+                map_value(r, offset, |r| self.skip(r))
+            })?,
+            Token::Tag(_) => self.skip(r)?,
+            Token::Break => return Err(UnexpectedCode::new::<Ipld>(0xff).into()),
         };
         Ok(())
     }
@@ -842,9 +764,13 @@ impl SkipOne for RawCborCodec {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{error::UnexpectedEof, RawCborCodec};
+    use crate::{
+        error::{CborError, UnexpectedEof},
+        RawCborCodec,
+    };
     use libipld_core::codec::Codec;
     use libipld_macro::ipld;
+    use multihash::{Code, MultihashDigest};
 
     #[test]
     fn il_map() {
@@ -873,13 +799,95 @@ mod tests {
             0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // very long
             0x01, // but only one byte.
         ];
-        RawCborCodec
+        let err = RawCborCodec
             .decode::<Ipld>(&bytes)
-            .expect_err("decoding large truncated buffer should have failed")
-            .downcast::<UnexpectedEof>()
+            .expect_err("decoding large truncated buffer should have failed");
+        assert_eq!(CborError::from(&err), CborError::UnexpectedEof);
+        err.downcast::<UnexpectedEof>()
             .expect("expected an unexpected eof");
     }
 
+    #[test]
+    fn location() {
+        // {"a": [1, "x"]}
+        let bytes = [0xa1, 0x61, 0x61, 0x82, 0x01, 0x61, 0x78];
+        let err = RawCborCodec
+            .decode::<BTreeMap<String, Vec<u8>>>(&bytes)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected cbor code `0x61` when decoding `u8`. (at offset 5, path [\"a\"][1])"
+        );
+        let location = err.downcast_ref::<Location>().unwrap();
+        assert_eq!(location.offset, 5);
+        assert_eq!(location.path, vec!["a".into(), 1.into()]);
+        assert_eq!(
+            CborError::from(&err),
+            CborError::UnexpectedCode {
+                code: 0x61,
+                ty: "u8"
+            }
+        );
+        err.downcast::<UnexpectedCode>().unwrap();
+
+        // {1: [[0x1c]]}, with a reserved code nested in a non-string key
+        let bytes = [0xa1, 0x01, 0x81, 0x81, 0x1c];
+        let err = RawCborCodec.decode::<Ipld>(&bytes).unwrap_err();
+        assert!(err.to_string().ends_with("(at offset 1)"));
+        let err = RawCborCodec
+            .references::<Ipld, _>(&bytes, &mut Vec::new())
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("(at offset 4, path [<01>][0][0])"));
+
+        let err = RawCborCodec
+            .decode::<(u8, bool)>(&[0x82, 0x01])
+            .unwrap_err();
+        assert!(err.to_string().ends_with("(at offset 2, path [1])"));
+        let err = RawCborCodec.decode::<u8>(&[0x61]).unwrap_err();
+        assert!(err.to_string().ends_with("(at offset 0)"));
+    }
+
+    #[test]
+    fn tags() {
+        let cid = Cid::new_v1(0x51, Code::Sha2_256.digest(b"tags"));
+        let link = RawCborCodec.encode(&cid).unwrap();
+
+        // tag 42 with a two byte head
+        let mut bytes = vec![0xd9, 0x00, 0x2a];
+        bytes.extend_from_slice(&link[2..]);
+        assert_eq!(RawCborCodec.decode::<Cid>(&bytes).unwrap(), cid);
+        assert_eq!(
+            RawCborCodec.decode::<Ipld>(&bytes).unwrap(),
+            Ipld::Link(cid)
+        );
+
+        // a link nested in tag 55799
+        let mut bytes = vec![0xd9, 0xd9, 0xf7];
+        bytes.extend_from_slice(&link);
+        let mut links = Vec::new();
+        RawCborCodec
+            .references::<Ipld, _>(&bytes, &mut links)
+            .unwrap();
+        assert_eq!(links, vec![cid]);
+        assert_eq!(
+            RawCborCodec.decode::<Ipld>(&bytes).unwrap(),
+            Ipld::Link(cid)
+        );
+
+        let err = RawCborCodec
+            .decode::<Ipld>(&[0xd9, 0x01, 0x02, 0x80])
+            .unwrap_err();
+        assert_eq!(err.downcast::<UnknownTag>().unwrap().0, 258);
+        let err = RawCborCodec
+            .decode::<Cid>(&[0xd8, 0x2a, 0x61, 0x78])
+            .unwrap_err();
+        assert_eq!(err.downcast::<InvalidLink>().unwrap().0, 0x61);
+        let err = RawCborCodec.decode::<String>(&[0x61, 0xff]).unwrap_err();
+        assert_eq!(CborError::from(&err), CborError::InvalidUtf8);
+    }
+
     #[test]
     #[allow(clippy::let_unit_value)]
     fn tuples() -> Result<()> {
@@ -909,4 +917,32 @@ mod tests {
 
         Ok(())
     }
+    #[test]
+    fn number_heads() {
+        // heads up to the width of the type, even if longer than needed
+        assert_eq!(RawCborCodec.decode::<u8>(&[0x18, 0x01]).unwrap(), 1);
+        assert_eq!(RawCborCodec.decode::<u16>(&[0x19, 0x00, 0x01]).unwrap(), 1);
+        assert_eq!(RawCborCodec.decode::<i8>(&[0x38, 0x7f]).unwrap(), -128);
+        let err = RawCborCodec.decode::<u8>(&[0x19, 0x00, 0x01]).unwrap_err();
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0x19);
+        let err = RawCborCodec.decode::<i16>(&[0x3a, 0, 0, 0, 1]).unwrap_err();
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0x3a);
+        let err = RawCborCodec.decode::<i8>(&[0x38, 0x80]).unwrap_err();
+        err.downcast::<NumberOutOfRange>().unwrap();
+
+        // f32 only from single precision, f64 also from double precision
+        let double = [0xfb, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0];
+        let half = [0xf9, 0x3c, 0x00];
+        assert!(RawCborCodec.decode::<f32>(&double).is_err());
+        assert!(RawCborCodec.decode::<f32>(&half).is_err());
+        assert!(RawCborCodec.decode::<f64>(&half).is_err());
+        assert_eq!(RawCborCodec.decode::<f64>(&double).unwrap(), 1.0);
+        let single = [0xfa, 0x3f, 0x80, 0, 0];
+        assert_eq!(RawCborCodec.decode::<f64>(&single).unwrap(), 1.0);
+        // except for the half floats the encoder writes
+        let bytes = RawCborCodec.encode(&f32::INFINITY).unwrap();
+        assert_eq!(RawCborCodec.decode::<f32>(&bytes).unwrap(), f32::INFINITY);
+        let bytes = RawCborCodec.encode(&f64::NAN).unwrap();
+        assert!(RawCborCodec.decode::<f64>(&bytes).unwrap().is_nan());
+    }
 }
@@ -1,17 +1,21 @@
 //! CBOR encoder.
-use crate::{error::NumberOutOfRange, RawCborCodec};
+use crate::{
+    error::NumberOutOfRange,
+    io::{Write, Writer},
+    RawCborCodec,
+};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use byteorder::{BigEndian, ByteOrder};
 use libipld_core::{cid::Cid, codec::Encode, error::Result, ipld::Ipld};
-use std::{collections::BTreeMap, io::Write, ops::Deref, sync::Arc};
 
 /// Writes a null byte to a cbor encoded byte stream.
-pub fn write_null<W: Write>(w: &mut W) -> Result<()> {
+pub fn write_null<W: Writer + ?Sized>(w: &mut W) -> Result<()> {
     w.write_all(&[0xf6])?;
     Ok(())
 }
 
 /// Writes a u8 to a cbor encoded byte stream.
-pub fn write_u8<W: Write>(w: &mut W, major: u8, value: u8) -> Result<()> {
+pub fn write_u8<W: Writer + ?Sized>(w: &mut W, major: u8, value: u8) -> Result<()> {
     if value <= 0x17 {
         let buf = [major << 5 | value];
         w.write_all(&buf)?;
@@ -23,8 +27,8 @@ pub fn write_u8<W: Write>(w: &mut W, major: u8, value: u8) -> Result<()> {
 }
 
 /// Writes a u16 to a cbor encoded byte stream.
-pub fn write_u16<W: Write>(w: &mut W, major: u8, value: u16) -> Result<()> {
-    if value <= u16::from(u8::max_value()) {
+pub fn write_u16<W: Writer + ?Sized>(w: &mut W, major: u8, value: u16) -> Result<()> {
+    if value <= u16::from(u8::MAX) {
         write_u8(w, major, value as u8)?;
     } else {
         let mut buf = [major << 5 | 25, 0, 0];
@@ -35,8 +39,8 @@ pub fn write_u16<W: Write>(w: &mut W, major: u8, value: u16) -> Result<()> {
 }
 
 /// Writes a u32 to a cbor encoded byte stream.
-pub fn write_u32<W: Write>(w: &mut W, major: u8, value: u32) -> Result<()> {
-    if value <= u32::from(u16::max_value()) {
+pub fn write_u32<W: Writer + ?Sized>(w: &mut W, major: u8, value: u32) -> Result<()> {
+    if value <= u32::from(u16::MAX) {
         write_u16(w, major, value as u16)?;
     } else {
         let mut buf = [major << 5 | 26, 0, 0, 0, 0];
@@ -47,8 +51,8 @@ pub fn write_u32<W: Write>(w: &mut W, major: u8, value: u32) -> Result<()> {
 }
 
 /// Writes a u64 to a cbor encoded byte stream.
-pub fn write_u64<W: Write>(w: &mut W, major: u8, value: u64) -> Result<()> {
-    if value <= u64::from(u32::max_value()) {
+pub fn write_u64<W: Writer + ?Sized>(w: &mut W, major: u8, value: u64) -> Result<()> {
+    if value <= u64::from(u32::MAX) {
         write_u32(w, major, value as u32)?;
     } else {
         let mut buf = [major << 5 | 27, 0, 0, 0, 0, 0, 0, 0, 0];
@@ -59,14 +63,14 @@ pub fn write_u64<W: Write>(w: &mut W, major: u8, value: u64) -> Result<()> {
 }
 
 /// Writes a tag to a cbor encoded byte stream.
-pub fn write_tag<W: Write>(w: &mut W, tag: u64) -> Result<()> {
+pub fn write_tag<W: Writer + ?Sized>(w: &mut W, tag: u64) -> Result<()> {
     write_u64(w, 6, tag)
 }
 
 impl Encode<RawCborCodec> for bool {
     fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
         let buf = if *self { [0xf5] } else { [0xf4] };
-        w.write_all(&buf)?;
+        Writer::write_all(w, &buf)?;
         Ok(())
     }
 }
@@ -124,16 +128,16 @@ impl Encode<RawCborCodec> for f32 {
     fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
         if self.is_infinite() {
             if self.is_sign_positive() {
-                w.write_all(&[0xf9, 0x7c, 0x00])?;
+                Writer::write_all(w, &[0xf9, 0x7c, 0x00])?;
             } else {
-                w.write_all(&[0xf9, 0xfc, 0x00])?;
+                Writer::write_all(w, &[0xf9, 0xfc, 0x00])?;
             }
         } else if self.is_nan() {
-            w.write_all(&[0xf9, 0x7e, 0x00])?;
+            Writer::write_all(w, &[0xf9, 0x7e, 0x00])?;
         } else {
             let mut buf = [0xfa, 0, 0, 0, 0];
             BigEndian::write_f32(&mut buf[1..], *self);
-            w.write_all(&buf)?;
+            Writer::write_all(w, &buf)?;
         }
         Ok(())
     }
@@ -148,7 +152,7 @@ impl Encode<RawCborCodec> for f64 {
         } else {
             let mut buf = [0xfb, 0, 0, 0, 0, 0, 0, 0, 0];
             BigEndian::write_f64(&mut buf[1..], *self);
-            w.write_all(&buf)?;
+            Writer::write_all(w, &buf)?;
         }
         Ok(())
     }
@@ -157,7 +161,7 @@ impl Encode<RawCborCodec> for f64 {
 impl Encode<RawCborCodec> for [u8] {
     fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
         write_u64(w, 2, self.len() as u64)?;
-        w.write_all(self)?;
+        Writer::write_all(w, self)?;
         Ok(())
     }
 }
@@ -171,7 +175,7 @@ impl Encode<RawCborCodec> for Box<[u8]> {
 impl Encode<RawCborCodec> for str {
     fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
         write_u64(w, 3, self.len() as u64)?;
-        w.write_all(self.as_bytes())?;
+        Writer::write_all(w, self.as_bytes())?;
         Ok(())
     }
 }
@@ -185,12 +189,12 @@ impl Encode<RawCborCodec> for String {
 impl Encode<RawCborCodec> for i128 {
     fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
         if *self < 0 {
-            if -(*self + 1) > u64::max_value() as i128 {
+            if -(*self + 1) > u64::MAX as i128 {
                 return Err(NumberOutOfRange::new::<i128>().into());
             }
             write_u64(w, 1, -(*self + 1) as u64)?;
         } else {
-            if *self > u64::max_value() as i128 {
+            if *self > u64::MAX as i128 {
                 return Err(NumberOutOfRange::new::<i128>().into());
             }
             write_u64(w, 0, *self as u64)?;
@@ -207,8 +211,8 @@ impl Encode<RawCborCodec> for Cid {
         let buf = self.to_bytes();
         let len = buf.len();
         write_u64(w, 2, len as u64 + 1)?;
-        w.write_all(&[0])?;
-        w.write_all(&buf[..len])?;
+        Writer::write_all(w, &[0])?;
+        Writer::write_all(w, &buf[..len])?;
         Ok(())
     }
 }
@@ -261,9 +265,16 @@ impl Encode<RawCborCodec> for Ipld {
     }
 }
 
+/// Within [`crate::sharing::encode`], repeated `Arc`s are written as references.
 impl<T: Encode<RawCborCodec>> Encode<RawCborCodec> for Arc<T> {
+    #[cfg(feature = "std")]
+    fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
+        crate::sharing::encode_arc(self, c, w)
+    }
+
+    #[cfg(not(feature = "std"))]
     fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
-        self.deref().encode(c, w)
+        (**self).encode(c, w)
     }
 }
 
@@ -0,0 +1,318 @@
+//! Computing the encoded length of values without encoding them.
+use crate::RawCborCodec;
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use libipld_core::{
+    cid::{Cid, Version},
+    ipld::Ipld,
+    raw_value::RawValue,
+};
+
+/// Number of bytes of a head with argument `value`, as written by `write_u64`.
+pub fn head_len(value: u64) -> usize {
+    if value <= 0x17 {
+        1
+    } else if value <= u64::from(u8::MAX) {
+        2
+    } else if value <= u64::from(u16::MAX) {
+        3
+    } else if value <= u64::from(u32::MAX) {
+        5
+    } else {
+        9
+    }
+}
+
+/// Number of bytes of an unsigned varint.
+fn varint_len(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).max(1).div_ceil(7)
+}
+
+/// Number of bytes of the binary representation of a cid.
+fn cid_bytes_len(cid: &Cid) -> usize {
+    let hash = cid.hash();
+    let hash_len = varint_len(hash.code()) + varint_len(hash.size().into()) + hash.digest().len();
+    match cid.version() {
+        Version::V0 => hash_len,
+        Version::V1 => varint_len(1) + varint_len(cid.codec()) + hash_len,
+    }
+}
+
+/// Types that can compute the length of their `Encode<RawCborCodec>` output up front.
+///
+/// This is useful to size buffers or to decide whether a value should be stored inline or in its
+/// own block.
+pub trait EncodedLen {
+    /// The exact number of bytes that encoding this value writes.
+    ///
+    /// For values that fail to encode, such as an `i128` outside of the cbor integer range, the
+    /// result is unspecified.
+    fn encoded_len(&self) -> usize;
+}
+
+impl<T: EncodedLen + ?Sized> EncodedLen for &T {
+    fn encoded_len(&self) -> usize {
+        (**self).encoded_len()
+    }
+}
+
+impl EncodedLen for bool {
+    fn encoded_len(&self) -> usize {
+        1
+    }
+}
+
+impl EncodedLen for u8 {
+    fn encoded_len(&self) -> usize {
+        head_len((*self).into())
+    }
+}
+
+impl EncodedLen for u16 {
+    fn encoded_len(&self) -> usize {
+        head_len((*self).into())
+    }
+}
+
+impl EncodedLen for u32 {
+    fn encoded_len(&self) -> usize {
+        head_len((*self).into())
+    }
+}
+
+impl EncodedLen for u64 {
+    fn encoded_len(&self) -> usize {
+        head_len(*self)
+    }
+}
+
+impl EncodedLen for i8 {
+    fn encoded_len(&self) -> usize {
+        head_len((-(*self + 1) as u8).into())
+    }
+}
+
+impl EncodedLen for i16 {
+    fn encoded_len(&self) -> usize {
+        head_len((-(*self + 1) as u16).into())
+    }
+}
+
+impl EncodedLen for i32 {
+    fn encoded_len(&self) -> usize {
+        head_len((-(*self + 1) as u32).into())
+    }
+}
+
+impl EncodedLen for i64 {
+    fn encoded_len(&self) -> usize {
+        head_len(-(*self + 1) as u64)
+    }
+}
+
+impl EncodedLen for i128 {
+    fn encoded_len(&self) -> usize {
+        if *self < 0 {
+            head_len(-(*self + 1) as u64)
+        } else {
+            head_len(*self as u64)
+        }
+    }
+}
+
+impl EncodedLen for f32 {
+    fn encoded_len(&self) -> usize {
+        if self.is_finite() {
+            5
+        } else {
+            3
+        }
+    }
+}
+
+impl EncodedLen for f64 {
+    #[allow(clippy::float_cmp)]
+    fn encoded_len(&self) -> usize {
+        if !self.is_finite() || f64::from(*self as f32) == *self {
+            (*self as f32).encoded_len()
+        } else {
+            9
+        }
+    }
+}
+
+impl EncodedLen for [u8] {
+    fn encoded_len(&self) -> usize {
+        head_len(self.len() as u64) + self.len()
+    }
+}
+
+impl EncodedLen for Box<[u8]> {
+    fn encoded_len(&self) -> usize {
+        self[..].encoded_len()
+    }
+}
+
+impl EncodedLen for str {
+    fn encoded_len(&self) -> usize {
+        self.as_bytes().encoded_len()
+    }
+}
+
+impl EncodedLen for String {
+    fn encoded_len(&self) -> usize {
+        self.as_str().encoded_len()
+    }
+}
+
+impl EncodedLen for Cid {
+    fn encoded_len(&self) -> usize {
+        // tag 42, then a byte string with a zero prefix byte
+        let len = cid_bytes_len(self) + 1;
+        2 + head_len(len as u64) + len
+    }
+}
+
+impl<T: EncodedLen> EncodedLen for Option<T> {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Some(value) => value.encoded_len(),
+            None => 1,
+        }
+    }
+}
+
+impl<T: EncodedLen> EncodedLen for Vec<T> {
+    fn encoded_len(&self) -> usize {
+        head_len(self.len() as u64) + self.iter().map(EncodedLen::encoded_len).sum::<usize>()
+    }
+}
+
+impl<K: EncodedLen, T: EncodedLen> EncodedLen for BTreeMap<K, T> {
+    fn encoded_len(&self) -> usize {
+        head_len(self.len() as u64)
+            + self
+                .iter()
+                .map(|(k, v)| k.encoded_len() + v.encoded_len())
+                .sum::<usize>()
+    }
+}
+
+impl EncodedLen for Ipld {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Self::Null => 1,
+            Self::Bool(b) => b.encoded_len(),
+            Self::Integer(i) => i.encoded_len(),
+            Self::Float(f) => f.encoded_len(),
+            Self::Bytes(b) => b.as_slice().encoded_len(),
+            Self::String(s) => s.encoded_len(),
+            Self::List(l) => l.encoded_len(),
+            Self::Map(m) => m.encoded_len(),
+            Self::Link(cid) => cid.encoded_len(),
+        }
+    }
+}
+
+impl<T: EncodedLen> EncodedLen for Arc<T> {
+    fn encoded_len(&self) -> usize {
+        (**self).encoded_len()
+    }
+}
+
+impl EncodedLen for RawValue<RawCborCodec> {
+    fn encoded_len(&self) -> usize {
+        self.as_ref().len()
+    }
+}
+
+impl EncodedLen for () {
+    fn encoded_len(&self) -> usize {
+        1
+    }
+}
+
+impl<A: EncodedLen> EncodedLen for (A,) {
+    fn encoded_len(&self) -> usize {
+        1 + self.0.encoded_len()
+    }
+}
+
+impl<A: EncodedLen, B: EncodedLen> EncodedLen for (A, B) {
+    fn encoded_len(&self) -> usize {
+        1 + self.0.encoded_len() + self.1.encoded_len()
+    }
+}
+
+impl<A: EncodedLen, B: EncodedLen, C: EncodedLen> EncodedLen for (A, B, C) {
+    fn encoded_len(&self) -> usize {
+        1 + self.0.encoded_len() + self.1.encoded_len() + self.2.encoded_len()
+    }
+}
+
+impl<A: EncodedLen, B: EncodedLen, C: EncodedLen, D: EncodedLen> EncodedLen for (A, B, C, D) {
+    fn encoded_len(&self) -> usize {
+        1 + self.0.encoded_len()
+            + self.1.encoded_len()
+            + self.2.encoded_len()
+            + self.3.encoded_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::codec::{Codec, Encode};
+    use libipld_macro::ipld;
+    use multihash::{Code, MultihashDigest};
+    use quickcheck::quickcheck;
+
+    fn check<T: Encode<RawCborCodec> + EncodedLen + ?Sized>(value: &T) -> bool {
+        RawCborCodec.encode(value).unwrap().len() == value.encoded_len()
+    }
+
+    quickcheck! {
+        fn unsigned(a: u8, b: u16, c: u32, d: u64) -> bool {
+            check(&(a, b, c, d))
+        }
+
+        fn negative(a: u8, b: u16, c: u32, d: u64) -> bool {
+            check(&(
+                -1 - (a >> 1) as i8,
+                -1 - (b >> 1) as i16,
+                -1 - (c >> 1) as i32,
+                -1 - (d >> 1) as i64,
+            ))
+        }
+
+        fn integer(a: i64, b: bool) -> bool {
+            check(&Ipld::Integer(if b { a as i128 - i64::MAX as i128 } else { a as i128 }))
+        }
+
+        fn floats(a: f32, b: f64) -> bool {
+            check(&(a, b))
+        }
+
+        fn strings(a: String, b: Vec<u8>) -> bool {
+            check(&(a, b.into_boxed_slice()))
+        }
+
+        fn containers(a: Vec<Option<u32>>, b: BTreeMap<String, Vec<u16>>) -> bool {
+            check(&(a, b))
+        }
+    }
+
+    #[test]
+    fn ipld() {
+        let v0 = Cid::new_v0(Code::Sha2_256.digest(b"v0")).unwrap();
+        let v1 = Cid::new_v1(0x51, Code::Blake3_256.digest(b"v1"));
+        let ipld = ipld!({
+            "null": null,
+            "list": [true, 1, -1000, 1.5, 1e300, "x"],
+            "bytes": vec![0u8; 300],
+            "links": [v0, v1],
+        });
+        assert!(check(&ipld));
+        assert!(check(&(v0,)));
+        assert!(check(&()));
+    }
+}
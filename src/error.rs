@@ -1,10 +1,14 @@
 //! CBOR error types.
-use std::any::type_name;
-use thiserror::Error;
+use crate::path::PathSegment;
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{any::type_name, fmt};
+use libipld_core::error::Error;
 
 /// Number larger than u64.
-#[derive(Debug, Error)]
-#[error("Number larger than {ty}.")]
+#[derive(Debug)]
 pub struct NumberOutOfRange {
     /// Type.
     pub ty: &'static str,
@@ -19,9 +23,16 @@ impl NumberOutOfRange {
     }
 }
 
+impl fmt::Display for NumberOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Number larger than {}.", self.ty)
+    }
+}
+
+impl core::error::Error for NumberOutOfRange {}
+
 /// Length larger than usize or too small, for example zero length cid field.
-#[derive(Debug, Error)]
-#[error("Length out of range when decoding {ty}.")]
+#[derive(Debug)]
 pub struct LengthOutOfRange {
     /// Type.
     pub ty: &'static str,
@@ -36,9 +47,16 @@ impl LengthOutOfRange {
     }
 }
 
+impl fmt::Display for LengthOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Length out of range when decoding {}.", self.ty)
+    }
+}
+
+impl core::error::Error for LengthOutOfRange {}
+
 /// Unexpected cbor code.
-#[derive(Debug, Error)]
-#[error("Unexpected cbor code `0x{code:x}` when decoding `{ty}`.")]
+#[derive(Debug)]
 pub struct UnexpectedCode {
     /// Code.
     pub code: u8,
@@ -56,9 +74,20 @@ impl UnexpectedCode {
     }
 }
 
+impl fmt::Display for UnexpectedCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unexpected cbor code `0x{:x}` when decoding `{}`.",
+            self.code, self.ty
+        )
+    }
+}
+
+impl core::error::Error for UnexpectedCode {}
+
 /// Unexpected key.
-#[derive(Debug, Error)]
-#[error("Unexpected key `{key}` when decoding `{ty}`.")]
+#[derive(Debug)]
 pub struct UnexpectedKey {
     /// Key.
     pub key: String,
@@ -76,9 +105,20 @@ impl UnexpectedKey {
     }
 }
 
+impl fmt::Display for UnexpectedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Unexpected key `{}` when decoding `{}`.",
+            self.key, self.ty
+        )
+    }
+}
+
+impl core::error::Error for UnexpectedKey {}
+
 /// Missing key.
-#[derive(Debug, Error)]
-#[error("Missing key `{key}` for decoding `{ty}`.")]
+#[derive(Debug)]
 pub struct MissingKey {
     /// Key.
     pub key: &'static str,
@@ -96,17 +136,405 @@ impl MissingKey {
     }
 }
 
+impl fmt::Display for MissingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Missing key `{}` for decoding `{}`.", self.key, self.ty)
+    }
+}
+
+impl core::error::Error for MissingKey {}
+
 /// Unknown cbor tag.
-#[derive(Debug, Error)]
-#[error("Unkown cbor tag `{0}`.")]
-pub struct UnknownTag(pub u8);
+#[derive(Debug)]
+pub struct UnknownTag(pub u64);
+
+impl fmt::Display for UnknownTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unkown cbor tag `{}`.", self.0)
+    }
+}
+
+impl core::error::Error for UnknownTag {}
 
 /// Unexpected eof.
-#[derive(Debug, Error)]
-#[error("Unexpected end of file.")]
+#[derive(Debug)]
 pub struct UnexpectedEof;
 
+impl fmt::Display for UnexpectedEof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Unexpected end of file.")
+    }
+}
+
+impl core::error::Error for UnexpectedEof {}
+
+/// The item after tag 42 was not a byte string.
+#[derive(Debug)]
+pub struct InvalidLink(pub u8);
+
+impl fmt::Display for InvalidLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid link: expected a byte string after tag 42 but found cbor code `0x{:x}`.",
+            self.0
+        )
+    }
+}
+
+impl core::error::Error for InvalidLink {}
+
 /// The byte before Cid was not multibase identity prefix.
-#[derive(Debug, Error)]
-#[error("Invalid Cid prefix: {0}")]
+#[derive(Debug)]
 pub struct InvalidCidPrefix(pub u8);
+
+impl fmt::Display for InvalidCidPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid Cid prefix: {}", self.0)
+    }
+}
+
+impl core::error::Error for InvalidCidPrefix {}
+
+/// A string reference (tag 25) whose index is not in the current stringref namespace.
+#[derive(Debug)]
+pub struct InvalidStringRef(pub u64);
+
+impl fmt::Display for InvalidStringRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid string reference `{}`.", self.0)
+    }
+}
+
+impl core::error::Error for InvalidStringRef {}
+
+/// A shared reference (tag 29) to a value that has not been decoded or has another type.
+#[derive(Debug)]
+pub struct InvalidSharedRef(pub u64);
+
+impl fmt::Display for InvalidSharedRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid shared reference `{}`.", self.0)
+    }
+}
+
+impl core::error::Error for InvalidSharedRef {}
+
+/// Data can not be converted to dag-cbor.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct NotDagCbor(pub crate::dag_cbor::Violation);
+
+#[cfg(feature = "std")]
+impl fmt::Display for NotDagCbor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Not convertible to dag-cbor: {}.", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::error::Error for NotDagCbor {}
+
+/// A block source does not have the block of a cid.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct BlockNotFound(pub libipld_core::cid::Cid);
+
+#[cfg(feature = "std")]
+impl fmt::Display for BlockNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Block `{}` not found.", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::error::Error for BlockNotFound {}
+
+/// Json does not follow the representation used by this crate.
+#[derive(Debug)]
+pub struct InvalidJson(pub &'static str);
+
+impl fmt::Display for InvalidJson {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid json: {}.", self.0)
+    }
+}
+
+impl core::error::Error for InvalidJson {}
+
+/// A cddl schema can not be parsed or uses features that are not supported.
+#[derive(Debug)]
+pub struct InvalidCddl(pub String);
+
+impl fmt::Display for InvalidCddl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid cddl: {}.", self.0)
+    }
+}
+
+impl core::error::Error for InvalidCddl {}
+
+/// An item does not match a cddl schema.
+#[derive(Debug)]
+pub struct SchemaMismatch {
+    /// The map keys and array indices leading from the validated item to the one that does not
+    /// match.
+    pub path: Vec<PathSegment>,
+    /// The rule containing the type that does not match.
+    pub rule: String,
+    /// The type or map entry that does not match, as written in the schema.
+    pub expected: String,
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Item does not match `{}` in rule `{}`",
+            self.expected, self.rule
+        )?;
+        if !self.path.is_empty() {
+            f.write_str(" at path ")?;
+            for segment in &self.path {
+                write!(f, "{}", segment)?;
+            }
+        }
+        f.write_str(".")
+    }
+}
+
+impl core::error::Error for SchemaMismatch {}
+
+/// Validating an item against a cddl schema nested more rules than the limit, e.g. because the
+/// item is nested that deeply under a recursive rule.
+#[derive(Debug)]
+pub struct ValidationTooDeep(pub usize);
+
+impl fmt::Display for ValidationTooDeep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Validation nested more than {} rules.", self.0)
+    }
+}
+
+impl core::error::Error for ValidationTooDeep {}
+
+/// Where a decode error occurred.
+///
+/// Decoding attaches this as context to errors, so it is shown in their `Display` output and can
+/// be inspected with `err.downcast_ref::<Location>()`, while downcasting to the underlying error
+/// keeps working.
+#[derive(Debug)]
+pub struct Location {
+    /// Byte offset of the item that failed to decode.
+    pub offset: u64,
+    /// The map keys and array indices leading from the outermost item to the failing one.
+    pub path: Vec<PathSegment>,
+    message: String,
+}
+
+impl Location {
+    /// Attaches the location of the item at `offset` to `err`, unless it already has one.
+    pub(crate) fn attach(err: Error, offset: u64) -> Error {
+        if err.is::<Self>() {
+            return err;
+        }
+        let message = err.to_string();
+        err.context(Self {
+            offset,
+            path: Vec::new(),
+            message,
+        })
+    }
+
+    /// Prepends `segment` to the path of the location of `err`, if it has one.
+    pub(crate) fn prepend(mut err: Error, segment: PathSegment) -> Error {
+        if let Some(location) = err.downcast_mut::<Self>() {
+            location.path.insert(0, segment);
+        }
+        err
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at offset {}", self.message, self.offset)?;
+        if !self.path.is_empty() {
+            f.write_str(", path ")?;
+            for segment in &self.path {
+                write!(f, "{}", segment)?;
+            }
+        }
+        f.write_str(")")
+    }
+}
+
+/// Every failure mode of this crate, for matching on errors without downcasting to each type.
+///
+/// Obtain it from a returned error with `CborError::from(&err)`. The [`Location`] of decode
+/// errors is not part of it and can be read with `err.downcast_ref::<Location>()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CborError {
+    /// See [`NumberOutOfRange`].
+    NumberOutOfRange {
+        /// Type.
+        ty: &'static str,
+    },
+    /// See [`LengthOutOfRange`].
+    LengthOutOfRange {
+        /// Type.
+        ty: &'static str,
+    },
+    /// See [`UnexpectedCode`].
+    UnexpectedCode {
+        /// Code.
+        code: u8,
+        /// Type.
+        ty: &'static str,
+    },
+    /// See [`UnexpectedKey`].
+    UnexpectedKey {
+        /// Key.
+        key: String,
+        /// Type.
+        ty: &'static str,
+    },
+    /// See [`MissingKey`].
+    MissingKey {
+        /// Key.
+        key: &'static str,
+        /// Type.
+        ty: &'static str,
+    },
+    /// See [`UnknownTag`].
+    UnknownTag(u64),
+    /// See [`UnexpectedEof`].
+    UnexpectedEof,
+    /// See [`InvalidCidPrefix`].
+    InvalidCidPrefix(u8),
+    /// See [`InvalidLink`].
+    InvalidLink(u8),
+    /// See [`InvalidStringRef`].
+    InvalidStringRef(u64),
+    /// See [`InvalidSharedRef`].
+    InvalidSharedRef(u64),
+    /// The bytes of a link are not a valid cid.
+    InvalidCid,
+    /// A text string is not valid utf-8.
+    InvalidUtf8,
+    /// See [`NotDagCbor`].
+    #[cfg(feature = "std")]
+    NotDagCbor(crate::dag_cbor::Violation),
+    /// See [`InvalidJson`].
+    InvalidJson(&'static str),
+    /// See [`InvalidCddl`].
+    InvalidCddl(String),
+    /// See [`SchemaMismatch`].
+    SchemaMismatch {
+        /// Path.
+        path: Vec<PathSegment>,
+        /// Rule.
+        rule: String,
+        /// Expected type.
+        expected: String,
+    },
+    /// See [`ValidationTooDeep`].
+    ValidationTooDeep(usize),
+    /// See [`BlockNotFound`].
+    #[cfg(feature = "std")]
+    BlockNotFound(libipld_core::cid::Cid),
+    /// The multicodec code is not supported.
+    UnsupportedCodec(u64),
+    /// The multihash code is not supported.
+    UnsupportedMultihash(u64),
+    /// The data does not match the hash of the cid.
+    InvalidMultihash,
+    /// Reading or writing failed.
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+    /// Any other error, e.g. one returned by a `Decode` implementation outside of this crate.
+    Other,
+}
+
+impl From<&Error> for CborError {
+    fn from(err: &Error) -> Self {
+        if let Some(e) = err.downcast_ref::<NumberOutOfRange>() {
+            Self::NumberOutOfRange { ty: e.ty }
+        } else if let Some(e) = err.downcast_ref::<LengthOutOfRange>() {
+            Self::LengthOutOfRange { ty: e.ty }
+        } else if let Some(e) = err.downcast_ref::<UnexpectedCode>() {
+            Self::UnexpectedCode {
+                code: e.code,
+                ty: e.ty,
+            }
+        } else if let Some(e) = err.downcast_ref::<UnexpectedKey>() {
+            Self::UnexpectedKey {
+                key: e.key.clone(),
+                ty: e.ty,
+            }
+        } else if let Some(e) = err.downcast_ref::<MissingKey>() {
+            Self::MissingKey {
+                key: e.key,
+                ty: e.ty,
+            }
+        } else if let Some(e) = err.downcast_ref::<UnknownTag>() {
+            Self::UnknownTag(e.0)
+        } else if err.is::<UnexpectedEof>() {
+            Self::UnexpectedEof
+        } else if let Some(e) = err.downcast_ref::<InvalidCidPrefix>() {
+            Self::InvalidCidPrefix(e.0)
+        } else if let Some(e) = err.downcast_ref::<InvalidLink>() {
+            Self::InvalidLink(e.0)
+        } else if let Some(e) = err.downcast_ref::<InvalidStringRef>() {
+            Self::InvalidStringRef(e.0)
+        } else if let Some(e) = err.downcast_ref::<InvalidSharedRef>() {
+            Self::InvalidSharedRef(e.0)
+        } else if err.is::<libipld_core::cid::Error>() {
+            Self::InvalidCid
+        } else if err.is::<alloc::string::FromUtf8Error>() || err.is::<core::str::Utf8Error>() {
+            Self::InvalidUtf8
+        } else if let Some(e) = err.downcast_ref::<InvalidJson>() {
+            Self::InvalidJson(e.0)
+        } else if let Some(e) = err.downcast_ref::<InvalidCddl>() {
+            Self::InvalidCddl(e.0.clone())
+        } else if let Some(e) = err.downcast_ref::<SchemaMismatch>() {
+            Self::SchemaMismatch {
+                path: e.path.clone(),
+                rule: e.rule.clone(),
+                expected: e.expected.clone(),
+            }
+        } else if let Some(e) = err.downcast_ref::<ValidationTooDeep>() {
+            Self::ValidationTooDeep(e.0)
+        } else {
+            Self::from_std(err)
+        }
+    }
+}
+
+impl CborError {
+    /// Classifies the errors that only implement `Error` with `std`.
+    #[cfg(feature = "std")]
+    fn from_std(err: &Error) -> Self {
+        use libipld_core::error::{InvalidMultihash, UnsupportedCodec, UnsupportedMultihash};
+        if let Some(e) = err.downcast_ref::<UnsupportedCodec>() {
+            Self::UnsupportedCodec(e.0)
+        } else if let Some(e) = err.downcast_ref::<UnsupportedMultihash>() {
+            Self::UnsupportedMultihash(e.0)
+        } else if err.is::<InvalidMultihash>() {
+            Self::InvalidMultihash
+        } else if let Some(e) = err.downcast_ref::<NotDagCbor>() {
+            Self::NotDagCbor(e.0)
+        } else if let Some(e) = err.downcast_ref::<BlockNotFound>() {
+            Self::BlockNotFound(e.0)
+        } else if let Some(e) = err.downcast_ref::<std::io::Error>() {
+            Self::Io(e.kind())
+        } else {
+            Self::Other
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn from_std(_: &Error) -> Self {
+        Self::Other
+    }
+}
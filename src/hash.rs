@@ -0,0 +1,104 @@
+//! Hashing encoded data while it is written.
+use crate::{RawCborCodec, CBOR};
+use libipld_core::{
+    cid::Cid,
+    codec::Encode,
+    error::Result,
+    multihash::{Hasher, MultihashGeneric},
+};
+use std::io::{self, Sink, Write};
+
+/// A writer that feeds everything written to it into a hasher, optionally passing it on to an
+/// inner writer.
+#[derive(Debug)]
+pub struct HashWriter<H, W = Sink> {
+    hasher: H,
+    inner: W,
+    len: u64,
+}
+
+impl<H: Hasher> HashWriter<H> {
+    /// Creates a writer that only hashes.
+    pub fn new(hasher: H) -> Self {
+        Self::tee(hasher, io::sink())
+    }
+}
+
+impl<H: Hasher, W: Write> HashWriter<H, W> {
+    /// Creates a writer that hashes and writes to `inner`.
+    pub fn tee(hasher: H, inner: W) -> Self {
+        Self {
+            hasher,
+            inner,
+            len: 0,
+        }
+    }
+
+    /// The number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the digest, the number of bytes written and the inner writer.
+    pub fn finish(mut self) -> (Vec<u8>, u64, W) {
+        let digest = self.hasher.finalize().to_vec();
+        (digest, self.len, self.inner)
+    }
+}
+
+impl<H: Hasher, W: Write> Write for HashWriter<H, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Computes the cid of `value` in a single pass without buffering the encoded bytes.
+///
+/// `hasher` must implement the hash function with multihash code `code`. Returns the cid and the
+/// encoded length.
+pub fn streaming_cid<H, T>(code: u64, hasher: H, value: &T) -> Result<(Cid, u64)>
+where
+    H: Hasher,
+    T: Encode<RawCborCodec> + ?Sized,
+{
+    let mut w = HashWriter::new(hasher);
+    value.encode(RawCborCodec, &mut w)?;
+    let (digest, len, _) = w.finish();
+    let hash = MultihashGeneric::wrap(code, &digest)?;
+    Ok((Cid::new_v1(CBOR, hash), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use libipld_macro::ipld;
+    use multihash::{Code, Sha2_256};
+
+    #[test]
+    fn matches_block() {
+        let ipld = ipld!({ "list": [1, 2, 3], "string": "hello" });
+        let block = Block::encode(Code::Sha2_256, &ipld).unwrap();
+        let (cid, len) = streaming_cid(Code::Sha2_256.into(), Sha2_256::default(), &ipld).unwrap();
+        assert_eq!(&cid, block.cid());
+        assert_eq!(len, block.data().len() as u64);
+    }
+
+    #[test]
+    fn tee() {
+        let mut w = HashWriter::tee(Sha2_256::default(), Vec::new());
+        ipld!([true, "x"]).encode(RawCborCodec, &mut w).unwrap();
+        let (digest, len, bytes) = w.finish();
+        let block = Block::encode(Code::Sha2_256, &ipld!([true, "x"])).unwrap();
+        assert_eq!(bytes, block.data());
+        assert_eq!(len, bytes.len() as u64);
+        assert_eq!(digest, block.cid().hash().digest());
+    }
+}
@@ -0,0 +1,260 @@
+//! Minimal reader and writer traits the encoder and decoder are built on.
+//!
+//! Both traits are implemented for every type implementing the io traits `libipld_core` uses,
+//! which are the ones from `std::io` with the `std` feature and the ones from `core2::io`
+//! without it. On embedded targets they can also be implemented directly for custom buffers.
+use crate::error::UnexpectedEof;
+use core::{any::TypeId, marker::PhantomData};
+use libipld_core::error::{Error, Result};
+
+#[cfg(not(feature = "std"))]
+pub(crate) use core2::io::{Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
+pub(crate) use std::io::{Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write};
+// decoding uses `SliceReader`, but the tests also read from cursors
+#[cfg(all(test, not(feature = "std")))]
+pub(crate) use core2::io::Cursor;
+#[cfg(all(test, feature = "std"))]
+pub(crate) use std::io::Cursor;
+
+/// A source of cbor encoded bytes.
+pub trait Reader {
+    /// Fills `buf` completely, failing with [`UnexpectedEof`] if the input ends first.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// Moves the read position `offset` bytes forward, or backward if it is negative.
+    fn advance(&mut self, offset: i64) -> Result<()>;
+
+    /// The read position, counted from the start of the input.
+    fn position(&mut self) -> Result<u64>;
+}
+
+/// A sink for cbor encoded bytes.
+pub trait Writer {
+    /// Writes all of `buf`.
+    fn write_all(&mut self, buf: &[u8]) -> Result<()>;
+}
+
+#[cfg(feature = "std")]
+fn io_error(err: std::io::Error) -> Error {
+    err.into()
+}
+
+/// Without `std` the io errors don't implement `Error`, so only their message is kept.
+#[cfg(not(feature = "std"))]
+fn io_error(err: core2::io::Error) -> Error {
+    Error::msg(err)
+}
+
+impl<R: Read + Seek + ?Sized> Reader for R {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        Read::read_exact(self, buf).map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => UnexpectedEof.into(),
+            _ => io_error(err),
+        })
+    }
+
+    fn advance(&mut self, offset: i64) -> Result<()> {
+        self.seek(SeekFrom::Current(offset)).map_err(io_error)?;
+        Ok(())
+    }
+
+    // `core2` has no `stream_position`
+    #[allow(clippy::seek_from_current)]
+    fn position(&mut self) -> Result<u64> {
+        self.seek(SeekFrom::Current(0)).map_err(io_error)
+    }
+}
+
+impl<W: Write + ?Sized> Writer for W {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        Write::write_all(self, buf).map_err(io_error)
+    }
+}
+
+/// A reader over an in-memory byte slice, used when decoding from a slice.
+///
+/// Seeking only moves an index, with or without `std`. The decoders of this crate recognize it
+/// behind their generic reader and then parse heads in place, copy strings out of the slice in one
+/// go, borrow the bytes of links and read positions without seeking.
+#[derive(Clone, Debug)]
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    /// Creates a reader positioned at the start of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    /// The bytes that have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bytes.get(self.pos..).unwrap_or_default()
+    }
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, IoError> {
+        let remaining = self.remaining();
+        let len = buf.len().min(remaining.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+
+    #[inline]
+    fn read_exact(&mut self, buf: &mut [u8]) -> core::result::Result<(), IoError> {
+        match self.remaining().get(..buf.len()) {
+            Some(bytes) => {
+                buf.copy_from_slice(bytes);
+                self.pos += buf.len();
+                Ok(())
+            }
+            None => Err(ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+/// The unread bytes and the position of a [`SliceReader`] the decoders found behind a generic
+/// reader.
+pub(crate) struct Slice<'r> {
+    bytes: &'r [u8],
+    pos: &'r mut usize,
+}
+
+impl<'r> Slice<'r> {
+    /// Returns the slice behind `r` if it is a [`SliceReader`].
+    #[inline]
+    pub(crate) fn of<R: ?Sized>(r: &'r mut R) -> Option<Self> {
+        if type_id::<R>() != type_id::<SliceReader<'static>>() {
+            return None;
+        }
+        let reader = (r as *mut R).cast::<SliceReader<'r>>();
+        // SAFETY: type ids don't depend on lifetimes, so `R` is a `SliceReader<'a>` with an `'a`
+        // that outlives `'r`. Only a copy of its slice, shortened to `'r`, and its position are
+        // handed out, so no reader with a shorter lifetime can be written back into `r`.
+        let (bytes, pos) = unsafe { ((*reader).bytes, &mut (*reader).pos) };
+        Some(Self { bytes, pos })
+    }
+
+    /// The read position, counted from the start of the input.
+    #[inline]
+    pub(crate) fn position(&self) -> u64 {
+        *self.pos as u64
+    }
+
+    /// Reads the next `len` bytes without copying them.
+    #[inline]
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'r [u8]> {
+        let bytes = self.bytes.get(*self.pos..).unwrap_or_default();
+        let bytes = bytes.get(..len).ok_or(UnexpectedEof)?;
+        *self.pos += len;
+        Ok(bytes)
+    }
+}
+
+/// The type id of `T`, which unlike [`TypeId::of`] doesn't require `T` to be `'static`.
+fn type_id<T: ?Sized>() -> TypeId {
+    trait NonStaticAny {
+        fn type_id(&self) -> TypeId
+        where
+            Self: 'static;
+    }
+
+    impl<T: ?Sized> NonStaticAny for PhantomData<T> {
+        fn type_id(&self) -> TypeId
+        where
+            Self: 'static,
+        {
+            TypeId::of::<T>()
+        }
+    }
+
+    let phantom = PhantomData::<T>;
+    let any: &dyn NonStaticAny = &phantom;
+    // SAFETY: lifetimes don't exist at runtime and `PhantomData` holds no data to outlive.
+    let any =
+        unsafe { core::mem::transmute::<&dyn NonStaticAny, &(dyn NonStaticAny + 'static)>(any) };
+    any.type_id()
+}
+
+/// Like a cursor, the position may be moved past the end of the slice.
+impl Seek for SliceReader<'_> {
+    #[inline]
+    fn seek(&mut self, pos: SeekFrom) -> core::result::Result<u64, IoError> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::End(offset) => (self.bytes.len(), offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+        match (base as i64)
+            .checked_add(offset)
+            .and_then(|pos| usize::try_from(pos).ok())
+        {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos as u64)
+            }
+            None => Err(ErrorKind::InvalidInput.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eof() {
+        let mut r = Cursor::new([1u8, 2, 3]);
+        let mut buf = [0; 2];
+        r.advance(1).unwrap();
+        assert_eq!(Reader::position(&mut r).unwrap(), 1);
+        Reader::read_exact(&mut r, &mut buf).unwrap();
+        assert_eq!(buf, [2, 3]);
+        r.advance(-1).unwrap();
+        Reader::read_exact(&mut r, &mut buf)
+            .unwrap_err()
+            .downcast::<UnexpectedEof>()
+            .unwrap();
+    }
+
+    #[test]
+    fn slice_reader() {
+        let mut r = SliceReader::new(&[1, 2, 3]);
+        let mut buf = [0; 2];
+        r.advance(1).unwrap();
+        Reader::read_exact(&mut r, &mut buf).unwrap();
+        assert_eq!(buf, [2, 3]);
+        assert_eq!(Reader::position(&mut r).unwrap(), 3);
+        assert!(r.remaining().is_empty());
+
+        // seeking past the end succeeds, reading there does not
+        r.advance(2).unwrap();
+        Reader::read_exact(&mut r, &mut buf[..1])
+            .unwrap_err()
+            .downcast::<UnexpectedEof>()
+            .unwrap();
+        assert!(r.advance(-6).is_err());
+        r.advance(-5).unwrap();
+        assert_eq!(r.remaining(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn slice() {
+        let mut r = SliceReader::new(&[1, 2, 3]);
+        let mut slice = Slice::of(&mut r).unwrap();
+        assert_eq!(slice.take(2).unwrap(), [1, 2]);
+        assert_eq!(slice.position(), 2);
+        slice
+            .take(2)
+            .unwrap_err()
+            .downcast::<UnexpectedEof>()
+            .unwrap();
+        assert_eq!(r.remaining(), [3]);
+        assert!(Slice::of(&mut Cursor::new([1u8, 2, 3])).is_none());
+        assert!(Slice::of(&mut &[1u8, 2, 3][..]).is_none());
+    }
+}
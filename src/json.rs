@@ -0,0 +1,328 @@
+//! Conversion between cbor and json.
+//!
+//! The mapping follows the dag-json conventions where possible and uses objects with the single
+//! key `"/"` for everything json can not express directly, so that every cbor item survives a
+//! roundtrip through json:
+//!
+//! | cbor                                | json                                        |
+//! |-------------------------------------|---------------------------------------------|
+//! | null, bool, text string, array      | null, bool, string, array                   |
+//! | integer in `i64` or `u64` range     | number                                      |
+//! | other integer                       | `{"/": {"int": "<decimal>"}}`               |
+//! | finite float                        | number with a fraction or exponent          |
+//! | NaN, infinity                       | `{"/": {"float": "NaN"}}`, `"Infinity"`, `"-Infinity"` |
+//! | byte string                         | `{"/": {"bytes": "<base64>"}}`              |
+//! | map with unique string keys         | object                                      |
+//! | other map, or map with only key `/` | `{"/": {"map": [[<key>, <value>], ...]}}`   |
+//! | tag 42                              | `{"/": "<cid>"}`                            |
+//! | other tag                           | `{"/": {"tag": <number>, "value": <value>}}` |
+//! | other simple value                  | `{"/": {"simple": <number>}}`               |
+//!
+//! Base64 is the standard alphabet without padding. The value survives the roundtrip, but not
+//! necessarily the exact bytes: json objects are sorted by key, indefinite lengths become definite
+//! and numbers are written with the shortest head.
+use crate::{
+    decode::{read_link, read_u8},
+    encode::{write_null, write_tag, write_u64},
+    error::{InvalidJson, UnexpectedCode},
+    io::SliceReader,
+    simple::Simple,
+    token::{items, read_payload, read_token_from, Token},
+    RawCborCodec,
+};
+use libipld_core::{
+    cid::Cid,
+    codec::{Codec, Encode},
+    error::Result,
+    ipld::Ipld,
+    multibase::Base,
+};
+use serde_json::{Map, Number, Value};
+use std::{
+    convert::TryFrom,
+    io::{Read, Seek, Write},
+};
+
+/// Wraps a value that json can not express directly.
+fn special(kind: &str, value: Value) -> Value {
+    let mut inner = Map::new();
+    inner.insert(kind.into(), value);
+    slash(Value::Object(inner))
+}
+
+fn slash(value: Value) -> Value {
+    let mut outer = Map::new();
+    outer.insert("/".into(), value);
+    Value::Object(outer)
+}
+
+fn float(value: f64) -> Value {
+    match Number::from_f64(value) {
+        Some(number) => Value::Number(number),
+        None if value.is_nan() => special("float", "NaN".into()),
+        None if value > 0.0 => special("float", "Infinity".into()),
+        None => special("float", "-Infinity".into()),
+    }
+}
+
+fn read_value<R: Read + Seek>(r: &mut R) -> Result<Value> {
+    let major = read_u8(r)?;
+    let value = match read_token_from(r, major)? {
+        Token::UInt(n) => n.into(),
+        Token::NInt(n) => match i64::try_from(n) {
+            Ok(n) => (-1 - n).into(),
+            Err(_) => special("int", (-1 - n as i128).to_string().into()),
+        },
+        token @ Token::Bytes(_) => {
+            special("bytes", Base::Base64.encode(read_payload(r, token)?).into())
+        }
+        token @ Token::Text(_) => String::from_utf8(read_payload(r, token)?)?.into(),
+        Token::ArrayStart(len) => {
+            let mut list = Vec::new();
+            items(r, len, |r, _| {
+                list.push(read_value(r)?);
+                Ok(())
+            })?;
+            Value::Array(list)
+        }
+        Token::MapStart(len) => {
+            let mut entries = Vec::new();
+            items(r, len, |r, _| {
+                entries.push((read_value(r)?, read_value(r)?));
+                Ok(())
+            })?;
+            let mut map = Map::new();
+            for (key, value) in &entries {
+                match key {
+                    Value::String(key) if !map.contains_key(key) => {
+                        map.insert(key.clone(), value.clone());
+                    }
+                    _ => break,
+                }
+            }
+            if map.len() == entries.len() && !(map.len() == 1 && map.contains_key("/")) {
+                Value::Object(map)
+            } else {
+                let entries = entries
+                    .into_iter()
+                    .map(|(key, value)| Value::Array(vec![key, value]))
+                    .collect();
+                special("map", Value::Array(entries))
+            }
+        }
+        Token::Tag(42) => slash(read_link(r)?.to_string().into()),
+        Token::Tag(tag) => {
+            let mut inner = Map::new();
+            inner.insert("tag".into(), tag.into());
+            inner.insert("value".into(), read_value(r)?);
+            slash(Value::Object(inner))
+        }
+        Token::Simple(20) => false.into(),
+        Token::Simple(21) => true.into(),
+        Token::Simple(22) => Value::Null,
+        Token::Simple(n) => special("simple", n.into()),
+        Token::Float(x) => float(x),
+        Token::Break => return Err(UnexpectedCode::new::<Value>(major).into()),
+    };
+    Ok(value)
+}
+
+/// Converts the first cbor item in `bytes` to json.
+pub fn to_json(bytes: &[u8]) -> Result<Value> {
+    read_value(&mut SliceReader::new(bytes))
+}
+
+fn write_special<W: Write>(w: &mut W, value: &Value) -> Result<()> {
+    let inner = match value {
+        Value::String(cid) => return Cid::try_from(cid.as_str())?.encode(RawCborCodec, w),
+        Value::Object(inner) => inner,
+        _ => return Err(InvalidJson("`/` must be a string or an object").into()),
+    };
+    let field = |key: &str| inner.get(key).ok_or(InvalidJson("missing field"));
+    let kind = match (inner.len(), inner.keys().next()) {
+        (2, _) if inner.contains_key("tag") => "tag",
+        (1, Some(kind)) => kind.as_str(),
+        _ => return Err(InvalidJson("unknown special value").into()),
+    };
+    match kind {
+        "bytes" => {
+            let bytes = field("bytes")?
+                .as_str()
+                .ok_or(InvalidJson("`bytes` must be a string"))?;
+            Base::Base64
+                .decode(bytes)?
+                .as_slice()
+                .encode(RawCborCodec, w)
+        }
+        "int" => {
+            let int = field("int")?
+                .as_str()
+                .ok_or(InvalidJson("`int` must be a string"))?;
+            int.parse::<i128>()?.encode(RawCborCodec, w)
+        }
+        "float" => {
+            let float: f64 = match field("float")?.as_str() {
+                Some("NaN") => f64::NAN,
+                Some("Infinity") => f64::INFINITY,
+                Some("-Infinity") => f64::NEG_INFINITY,
+                _ => return Err(InvalidJson("unknown `float` value").into()),
+            };
+            float.encode(RawCborCodec, w)
+        }
+        "simple" => {
+            // false, true and null have their own json values
+            let simple = field("simple")?
+                .as_u64()
+                .and_then(|simple| u8::try_from(simple).ok())
+                .filter(|simple| !(20..=22).contains(simple))
+                .and_then(Simple::new)
+                .ok_or(InvalidJson("`simple` must be 0 to 19, 23 or 32 to 255"))?;
+            simple.encode(RawCborCodec, w)
+        }
+        "map" => {
+            let entries = field("map")?
+                .as_array()
+                .ok_or(InvalidJson("`map` must be an array"))?;
+            write_u64(w, 5, entries.len() as u64)?;
+            for entry in entries {
+                match entry.as_array().map(Vec::as_slice) {
+                    Some([key, value]) => {
+                        write_value(w, key)?;
+                        write_value(w, value)?;
+                    }
+                    _ => return Err(InvalidJson("map entries must be pairs").into()),
+                }
+            }
+            Ok(())
+        }
+        "tag" => {
+            let tag = field("tag")?
+                .as_u64()
+                .ok_or(InvalidJson("`tag` must be a number"))?;
+            write_tag(w, tag)?;
+            write_value(w, field("value")?)
+        }
+        _ => Err(InvalidJson("unknown special value").into()),
+    }
+}
+
+fn write_value<W: Write>(w: &mut W, value: &Value) -> Result<()> {
+    match value {
+        Value::Null => write_null(w),
+        Value::Bool(b) => b.encode(RawCborCodec, w),
+        Value::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                n.encode(RawCborCodec, w)
+            } else if let Some(n) = n.as_i64() {
+                i128::from(n).encode(RawCborCodec, w)
+            } else {
+                n.as_f64()
+                    .ok_or(InvalidJson("number out of range"))?
+                    .encode(RawCborCodec, w)
+            }
+        }
+        Value::String(s) => s.encode(RawCborCodec, w),
+        Value::Array(list) => {
+            write_u64(w, 4, list.len() as u64)?;
+            for value in list {
+                write_value(w, value)?;
+            }
+            Ok(())
+        }
+        Value::Object(map) => match map.get("/") {
+            Some(value) if map.len() == 1 => write_special(w, value),
+            _ => {
+                write_u64(w, 5, map.len() as u64)?;
+                for (key, value) in map {
+                    key.encode(RawCborCodec, w)?;
+                    write_value(w, value)?;
+                }
+                Ok(())
+            }
+        },
+    }
+}
+
+/// Converts json produced by [`to_json`] back to cbor.
+pub fn from_json(value: &Value) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_value(&mut bytes, value)?;
+    Ok(bytes)
+}
+
+/// Converts json to [`Ipld`] by way of cbor, resolving links and bytes.
+pub fn json_to_ipld(value: &Value) -> Result<Ipld> {
+    RawCborCodec.decode(&from_json(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_macro::ipld;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn roundtrip(cbor: &[u8], json: Value) {
+        assert_eq!(to_json(cbor).unwrap(), json);
+        assert_eq!(from_json(&json).unwrap(), cbor);
+    }
+
+    #[test]
+    fn dag_json_conventions() {
+        let cid: Cid = "bafyreihtx752fmf3zafbys5dtr4jxohb53yi3qtzfzf6wd5274jwtn5agu"
+            .parse()
+            .unwrap();
+        let ipld = ipld!({
+            "bytes": vec![0u8, 1, 2, 3],
+            "link": cid,
+            "list": [1, -2, 1.5, "x", null, true],
+        });
+        let bytes = RawCborCodec.encode(&ipld).unwrap();
+        roundtrip(
+            &bytes,
+            json!({
+                "bytes": { "/": { "bytes": "AAECAw" } },
+                "link": { "/": "bafyreihtx752fmf3zafbys5dtr4jxohb53yi3qtzfzf6wd5274jwtn5agu" },
+                "list": [1, -2, 1.5, "x", null, true],
+            }),
+        );
+        assert_eq!(json_to_ipld(&to_json(&bytes).unwrap()).unwrap(), ipld);
+    }
+
+    #[test]
+    fn non_string_keys() {
+        let mut map = BTreeMap::new();
+        map.insert(1u8, "a".to_string());
+        map.insert(2u8, "b".to_string());
+        let bytes = RawCborCodec.encode(&map).unwrap();
+        roundtrip(&bytes, json!({ "/": { "map": [[1, "a"], [2, "b"]] } }));
+
+        let mut map = BTreeMap::new();
+        map.insert("/".to_string(), 1u8);
+        let bytes = RawCborCodec.encode(&map).unwrap();
+        roundtrip(&bytes, json!({ "/": { "map": [["/", 1]] } }));
+    }
+
+    #[test]
+    fn special_values() {
+        roundtrip(&[0xc1, 0x01], json!({ "/": { "tag": 1, "value": 1 } }));
+        roundtrip(&[0xf7], json!({ "/": { "simple": 23 } }));
+        roundtrip(&[0xf9, 0x7e, 0x00], json!({ "/": { "float": "NaN" } }));
+        roundtrip(
+            &[0xf9, 0xfc, 0x00],
+            json!({ "/": { "float": "-Infinity" } }),
+        );
+        roundtrip(
+            &[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            json!({ "/": { "int": "-18446744073709551616" } }),
+        );
+        assert!(from_json(&json!({ "/": { "unknown": 1 } })).is_err());
+        assert!(from_json(&json!({ "/": 1 })).is_err());
+        // simple values that need an extra byte start at 32, and 20 to 22 are bool and null
+        for simple in [20, 24, 31, 256] {
+            let err = from_json(&json!({ "/": { "simple": simple } })).unwrap_err();
+            err.downcast::<InvalidJson>().unwrap();
+        }
+        roundtrip(&[0xf8, 0x20], json!({ "/": { "simple": 32 } }));
+    }
+}
@@ -1,32 +1,115 @@
 //! CBOR codec.
 #![deny(missing_docs)]
 #![deny(warnings)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+use crate::{error::Location, io::SliceReader};
+use alloc::vec::Vec;
 use core::convert::TryFrom;
-use libipld_core::codec::{Codec, Decode, Encode};
 pub use libipld_core::error::{Result, UnsupportedCodec};
+use libipld_core::{
+    cid::Cid,
+    codec::{Codec, Decode, Encode, References},
+};
 
+#[cfg(feature = "std")]
+pub mod block;
+#[cfg(feature = "std")]
+pub mod cddl;
+#[cfg(feature = "std")]
+pub mod dag_cbor;
 pub mod decode;
 pub mod encode;
+pub mod encoded_len;
 pub mod error;
+#[cfg(feature = "std")]
+pub mod hash;
+pub mod io;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod link;
+#[cfg(feature = "std")]
+pub mod multicodec;
+pub mod options;
+pub mod path;
+pub mod self_described;
+#[cfg(feature = "std")]
+pub mod sharing;
+pub mod simple;
+pub mod stringref;
+pub mod token;
+#[cfg(feature = "std")]
+pub mod transcode;
+pub mod typed_array;
+pub mod value;
+#[cfg(feature = "std")]
+pub mod view;
 
 /// Raw CBOR codec.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RawCborCodec;
 
-impl Codec for RawCborCodec {}
+/// Runs `f` outside of any [`sharing`] scope, so that nested calls of the codec don't share
+/// `Arc`s with the outer one.
+#[cfg(feature = "std")]
+pub(crate) fn unshared<O>(f: impl FnOnce() -> O) -> O {
+    sharing::unshared(f)
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn unshared<O>(f: impl FnOnce() -> O) -> O {
+    f()
+}
+
+/// Decode errors carry an [`error::Location`] relative to the start of `bytes`.
+impl Codec for RawCborCodec {
+    fn encode<T: Encode<Self> + ?Sized>(&self, obj: &T) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(u16::MAX as usize);
+        unshared(|| obj.encode(*self, &mut buf))?;
+        Ok(buf)
+    }
+
+    fn decode<T: Decode<Self>>(&self, bytes: &[u8]) -> Result<T> {
+        unshared(|| T::decode(*self, &mut SliceReader::new(bytes)))
+            .map_err(|err| Location::attach(err, 0))
+    }
+
+    fn references<T: References<Self>, E: Extend<Cid>>(
+        &self,
+        bytes: &[u8],
+        set: &mut E,
+    ) -> Result<()> {
+        T::references(*self, &mut SliceReader::new(bytes), set)
+            .map_err(|err| Location::attach(err, 0))
+    }
+}
+
+/// Multicodec code of cbor.
+pub const CBOR: u64 = 0x51;
+
+/// Multicodec code of dag-cbor.
+pub const DAG_CBOR: u64 = 0x71;
 
 impl From<RawCborCodec> for u64 {
     fn from(_: RawCborCodec) -> Self {
-        0x51
+        CBOR
     }
 }
 
+/// Accepts the cbor multicodec code. With the `dag-cbor-compat` feature, the dag-cbor code is
+/// accepted as well, since dag-cbor is a subset of cbor.
 impl TryFrom<u64> for RawCborCodec {
     type Error = UnsupportedCodec;
 
-    fn try_from(_: u64) -> core::result::Result<Self, Self::Error> {
-        Ok(Self)
+    fn try_from(code: u64) -> core::result::Result<Self, Self::Error> {
+        match code {
+            CBOR => Ok(Self),
+            #[cfg(feature = "dag-cbor-compat")]
+            DAG_CBOR => Ok(Self),
+            _ => Err(UnsupportedCodec(code)),
+        }
     }
 }
 
@@ -38,7 +121,6 @@ impl<T: Encode<RawCborCodec> + Decode<RawCborCodec>> Cbor42 for T {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use libipld_core::cid::Cid;
     use libipld_core::ipld::Ipld;
     use libipld_macro::ipld;
     use multihash::{Code, MultihashDigest};
@@ -70,4 +152,15 @@ mod tests {
         RawCborCodec.references::<Ipld, _>(&bytes, &mut set).unwrap();
         assert!(set.contains(&cid));
     }
+
+    #[test]
+    fn test_codec_code() {
+        assert_eq!(u64::from(RawCborCodec), 0x51);
+        assert!(RawCborCodec::try_from(0x51).is_ok());
+        assert!(RawCborCodec::try_from(0x55).is_err());
+        assert_eq!(
+            RawCborCodec::try_from(0x71).is_ok(),
+            cfg!(feature = "dag-cbor-compat")
+        );
+    }
 }
@@ -0,0 +1,227 @@
+//! Links that know the type of their target.
+use crate::{
+    encoded_len::EncodedLen,
+    io::{Read, Seek, Write},
+    RawCborCodec,
+};
+use core::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+use libipld_core::{
+    cid::Cid,
+    codec::{Decode, Encode},
+    error::Result,
+};
+#[cfg(feature = "std")]
+use {
+    crate::{block::Block, error::BlockNotFound, CBOR},
+    libipld_core::{error::UnsupportedCodec, multihash::MultihashDigest},
+    std::{
+        collections::{BTreeMap, HashMap},
+        hash::BuildHasher,
+    },
+};
+
+/// A cid pointing to a block that decodes as `T`.
+///
+/// Encodes and decodes exactly like [`Cid`], so the target type only exists in the program.
+pub struct Link<T> {
+    cid: Cid,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Link<T> {
+    /// Creates a link to the block with `cid`, which is not checked to decode as `T`.
+    pub fn new(cid: Cid) -> Self {
+        Self {
+            cid,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The cid of the link.
+    pub fn cid(&self) -> &Cid {
+        &self.cid
+    }
+}
+
+impl<T> From<Cid> for Link<T> {
+    fn from(cid: Cid) -> Self {
+        Self::new(cid)
+    }
+}
+
+impl<T> From<Link<T>> for Cid {
+    fn from(link: Link<T>) -> Self {
+        link.cid
+    }
+}
+
+// implemented by hand, since deriving would require the same traits of `T`
+
+impl<T> Clone for Link<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Link<T> {}
+
+impl<T> fmt::Debug for Link<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Link").field(&self.cid).finish()
+    }
+}
+
+impl<T> PartialEq for Link<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cid == other.cid
+    }
+}
+
+impl<T> Eq for Link<T> {}
+
+impl<T> PartialOrd for Link<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Link<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cid.cmp(&other.cid)
+    }
+}
+
+impl<T> Hash for Link<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&self.cid, state)
+    }
+}
+
+impl<T> Encode<RawCborCodec> for Link<T> {
+    fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
+        self.cid.encode(c, w)
+    }
+}
+
+impl<T> Decode<RawCborCodec> for Link<T> {
+    fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
+        Ok(Self::new(Cid::decode(c, r)?))
+    }
+}
+
+impl<T> EncodedLen for Link<T> {
+    fn encoded_len(&self) -> usize {
+        self.cid.encoded_len()
+    }
+}
+
+/// A store blocks can be loaded from.
+#[cfg(feature = "std")]
+pub trait BlockSource {
+    /// Returns the data of the block with `cid`, or fails with [`BlockNotFound`].
+    fn get(&self, cid: &Cid) -> Result<Vec<u8>>;
+}
+
+#[cfg(feature = "std")]
+impl<S: BuildHasher> BlockSource for HashMap<Cid, Vec<u8>, S> {
+    fn get(&self, cid: &Cid) -> Result<Vec<u8>> {
+        HashMap::get(self, cid)
+            .cloned()
+            .ok_or_else(|| BlockNotFound(*cid).into())
+    }
+}
+
+#[cfg(feature = "std")]
+impl BlockSource for BTreeMap<Cid, Vec<u8>> {
+    fn get(&self, cid: &Cid) -> Result<Vec<u8>> {
+        BTreeMap::get(self, cid)
+            .cloned()
+            .ok_or_else(|| BlockNotFound(*cid).into())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Decode<RawCborCodec>> Link<T> {
+    /// Loads the block of the link from `source`, verifies it using the hash functions of `H`
+    /// and decodes it.
+    ///
+    /// Fails with [`UnsupportedCodec`] if the codec of the cid is not cbor, even if dag-cbor is
+    /// accepted elsewhere.
+    pub fn resolve<H, S>(&self, source: &S) -> Result<T>
+    where
+        H: MultihashDigest<64>,
+        S: BlockSource + ?Sized,
+    {
+        if self.cid.codec() != CBOR {
+            return Err(UnsupportedCodec(self.cid.codec()).into());
+        }
+        let data = source.get(&self.cid)?;
+        Block::new::<H>(self.cid, data)?.decode()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Encode<RawCborCodec>> Link<T> {
+    /// Encodes `value` into a block using the hash function `hcode`, returning a link to it
+    /// together with the block.
+    pub fn encode_block<H: MultihashDigest<64>>(hcode: H, value: &T) -> Result<(Self, Block)> {
+        let block = Block::encode(hcode, value)?;
+        Ok((Self::new(*block.cid()), block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::CborError, DAG_CBOR};
+    use libipld_core::{codec::Codec, error::InvalidMultihash};
+    use multihash::Code;
+
+    #[test]
+    fn wire_format() {
+        let cid = Cid::new_v1(CBOR, Code::Sha2_256.digest(b"target"));
+        let link = Link::<String>::new(cid);
+        let bytes = RawCborCodec.encode(&link).unwrap();
+        assert_eq!(bytes, RawCborCodec.encode(&cid).unwrap());
+        assert_eq!(link.encoded_len(), bytes.len());
+        assert_eq!(RawCborCodec.decode::<Link<String>>(&bytes).unwrap(), link);
+        assert_eq!(Cid::from(link), cid);
+    }
+
+    #[test]
+    fn resolve() {
+        let mut store = HashMap::new();
+        let (leaf, block) = Link::encode_block(Code::Sha2_256, &vec![1u8, 2, 3]).unwrap();
+        let (cid, data) = block.into_inner();
+        store.insert(cid, data);
+        let (root, block) =
+            Link::encode_block(Code::Blake3_256, &("root".to_string(), leaf)).unwrap();
+        let (cid, data) = block.into_inner();
+        store.insert(cid, data.clone());
+
+        let (name, leaf) = root.resolve::<Code, _>(&store).unwrap();
+        assert_eq!(name, "root");
+        assert_eq!(leaf.resolve::<Code, _>(&store).unwrap(), vec![1, 2, 3]);
+
+        // a block that doesn't match its cid
+        store.insert(*leaf.cid(), data);
+        let err = leaf.resolve::<Code, _>(&store).unwrap_err();
+        err.downcast::<InvalidMultihash>().unwrap();
+
+        // a missing block and a cid of another codec
+        let missing = Link::<u8>::new(Cid::new_v1(CBOR, Code::Sha2_256.digest(b"missing")));
+        let err = missing.resolve::<Code, _>(&store).unwrap_err();
+        assert_eq!(
+            CborError::from(&err),
+            CborError::BlockNotFound(*missing.cid())
+        );
+        let dag_cbor = Link::<u8>::new(Cid::new_v1(DAG_CBOR, *missing.cid().hash()));
+        let err = dag_cbor.resolve::<Code, _>(&BTreeMap::new()).unwrap_err();
+        assert_eq!(CborError::from(&err), CborError::UnsupportedCodec(DAG_CBOR));
+    }
+}
@@ -0,0 +1,134 @@
+//! Dispatching blocks to a decoder by their multicodec code.
+use crate::{dag_cbor::to_dag_cbor, RawCborCodec, CBOR, DAG_CBOR};
+use core::convert::TryFrom;
+use libipld_core::{
+    cid::Cid,
+    codec::{Codec, Decode, Encode, References},
+    error::{Result, UnsupportedCodec},
+    ipld::Ipld,
+    raw::RawCodec,
+};
+use std::io::{Read, Seek, Write};
+
+/// Multicodec code of raw bytes.
+pub const RAW: u64 = 0x55;
+
+/// The codecs a block can be decoded with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlockCodec {
+    /// Cbor, decoded with [`RawCborCodec`].
+    Cbor,
+    /// Dag-cbor, decoded with [`RawCborCodec`] and encoded in canonical dag-cbor form.
+    ///
+    /// Only recognised from its code with the `dag-cbor-compat` feature.
+    DagCbor,
+    /// Raw bytes, decoded with [`RawCodec`].
+    Raw,
+}
+
+impl BlockCodec {
+    /// Returns the codec for the multicodec code of `cid`.
+    pub fn from_cid(cid: &Cid) -> core::result::Result<Self, UnsupportedCodec> {
+        Self::try_from(cid.codec())
+    }
+}
+
+impl Codec for BlockCodec {}
+
+impl From<BlockCodec> for u64 {
+    fn from(c: BlockCodec) -> Self {
+        match c {
+            BlockCodec::Cbor => CBOR,
+            BlockCodec::DagCbor => DAG_CBOR,
+            BlockCodec::Raw => RAW,
+        }
+    }
+}
+
+impl TryFrom<u64> for BlockCodec {
+    type Error = UnsupportedCodec;
+
+    fn try_from(code: u64) -> core::result::Result<Self, Self::Error> {
+        match code {
+            CBOR => Ok(Self::Cbor),
+            #[cfg(feature = "dag-cbor-compat")]
+            DAG_CBOR => Ok(Self::DagCbor),
+            RAW => Ok(Self::Raw),
+            _ => Err(UnsupportedCodec(code)),
+        }
+    }
+}
+
+impl Encode<BlockCodec> for Ipld {
+    fn encode<W: Write>(&self, c: BlockCodec, w: &mut W) -> Result<()> {
+        match c {
+            BlockCodec::Cbor => self.encode(RawCborCodec, w),
+            BlockCodec::DagCbor => {
+                w.write_all(&to_dag_cbor(&RawCborCodec.encode(self)?)?)?;
+                Ok(())
+            }
+            BlockCodec::Raw => self.encode(RawCodec, w),
+        }
+    }
+}
+
+impl Decode<BlockCodec> for Ipld {
+    fn decode<R: Read + Seek>(c: BlockCodec, r: &mut R) -> Result<Self> {
+        match c {
+            BlockCodec::Cbor | BlockCodec::DagCbor => Self::decode(RawCborCodec, r),
+            BlockCodec::Raw => Self::decode(RawCodec, r),
+        }
+    }
+}
+
+impl References<BlockCodec> for Ipld {
+    fn references<R: Read + Seek, E: Extend<Cid>>(
+        c: BlockCodec,
+        r: &mut R,
+        set: &mut E,
+    ) -> Result<()> {
+        match c {
+            BlockCodec::Cbor | BlockCodec::DagCbor => {
+                <Self as References<RawCborCodec>>::references(RawCborCodec, r, set)
+            }
+            BlockCodec::Raw => <Self as References<RawCodec>>::references(RawCodec, r, set),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_macro::ipld;
+    use multihash::{Code, MultihashDigest};
+
+    #[test]
+    fn dispatch() {
+        let ipld = ipld!({ "b": 1.5, "aa": [true] });
+        let bytes = RawCborCodec.encode(&ipld).unwrap();
+        let cid = Cid::new_v1(CBOR, Code::Sha2_256.digest(&bytes));
+        let c = BlockCodec::from_cid(&cid).unwrap();
+        assert_eq!(c, BlockCodec::Cbor);
+        assert_eq!(c.encode(&ipld).unwrap(), bytes);
+        assert_eq!(c.decode::<Ipld>(&bytes).unwrap(), ipld);
+
+        let cid = Cid::new_v1(RAW, Code::Sha2_256.digest(&bytes));
+        let c = BlockCodec::from_cid(&cid).unwrap();
+        assert_eq!(c.decode::<Ipld>(&bytes).unwrap(), Ipld::Bytes(bytes));
+
+        let cid = Cid::new_v1(0x70, Code::Sha2_256.digest(b""));
+        assert_eq!(BlockCodec::from_cid(&cid).unwrap_err().0, 0x70);
+    }
+
+    #[test]
+    fn dag_cbor() {
+        let ipld = ipld!({ "b": 1.5, "aa": [true] });
+        let bytes = BlockCodec::DagCbor.encode(&ipld).unwrap();
+        assert!(crate::dag_cbor::check(&bytes).unwrap().is_empty());
+        assert_eq!(BlockCodec::DagCbor.decode::<Ipld>(&bytes).unwrap(), ipld);
+        assert_eq!(
+            BlockCodec::try_from(DAG_CBOR).is_ok(),
+            cfg!(feature = "dag-cbor-compat")
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Options changing how values are decoded.
+//!
+//! `Decode` implementations have no access to configuration, so [`DecodeOptions::decode`] makes
+//! its options current for the duration of the call and the implementations in this crate read
+//! them from there. Without `std` the default options always apply.
+#[cfg(feature = "std")]
+use crate::RawCborCodec;
+#[cfg(feature = "std")]
+use libipld_core::{
+    codec::{Codec, Decode},
+    error::Result,
+};
+
+/// How undefined (`0xf7`) is decoded into types that have no undefined value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UndefinedMode {
+    /// Undefined decodes like null, e.g. as `Ipld::Null` and `None`.
+    #[default]
+    Null,
+    /// Undefined fails to decode with an `UnexpectedCode` error, except into
+    /// [`Undefined`](crate::simple::Undefined) and [`Simple`](crate::simple::Simple).
+    Error,
+    /// `Option<T>` passes undefined on to `T`, so `Option<Undefined>` and `Option<Simple>` keep
+    /// it. Types that can't represent it, such as `Ipld`, fail like with [`UndefinedMode::Error`].
+    Preserve,
+}
+
+/// Which encodings of numbers the integer and float types accept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumberMode {
+    /// Integers must be encoded as integers of the right sign with a head no wider than the
+    /// type, and floats as floats no wider than the type. Heads may be longer than needed.
+    #[default]
+    Normal,
+    /// Accepts any number that converts to the type without loss, such as a float with an
+    /// integral value for an integer, an integer for a float, an unsigned integer for a signed
+    /// type, a head wider than the type or a double for an `f32`.
+    Lenient,
+    /// Like [`NumberMode::Normal`], but heads must also be as short as possible and floats must
+    /// not be wider than the encoder of this crate writes them: doubles only for values that
+    /// don't fit 32 bits, and infinity and NaN with 16 bits.
+    Strict,
+}
+
+/// Options for decoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    undefined: UndefinedMode,
+    numbers: NumberMode,
+}
+
+impl DecodeOptions {
+    /// The default options, which are what [`RawCborCodec`](crate::RawCborCodec) decodes with.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how undefined is decoded.
+    pub fn undefined(mut self, mode: UndefinedMode) -> Self {
+        self.undefined = mode;
+        self
+    }
+
+    /// How undefined is decoded.
+    pub fn undefined_mode(&self) -> UndefinedMode {
+        self.undefined
+    }
+
+    /// Sets which encodings of numbers are accepted.
+    pub fn numbers(mut self, mode: NumberMode) -> Self {
+        self.numbers = mode;
+        self
+    }
+
+    /// Which encodings of numbers are accepted.
+    pub fn number_mode(&self) -> NumberMode {
+        self.numbers
+    }
+
+    /// Decodes `bytes` with these options.
+    #[cfg(feature = "std")]
+    pub fn decode<T: Decode<RawCborCodec>>(&self, bytes: &[u8]) -> Result<T> {
+        struct Restore(DecodeOptions);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(*self)));
+        RawCborCodec.decode(bytes)
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CURRENT: core::cell::Cell<DecodeOptions> = core::cell::Cell::new(DecodeOptions::new());
+}
+
+/// The options of the innermost [`DecodeOptions::decode`] call.
+#[cfg(feature = "std")]
+pub(crate) fn current() -> DecodeOptions {
+    CURRENT.with(|current| current.get())
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn current() -> DecodeOptions {
+    DecodeOptions::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{NumberOutOfRange, UnexpectedCode},
+        simple::Undefined,
+    };
+    use libipld_core::{codec::Codec, ipld::Ipld};
+
+    #[test]
+    fn undefined() {
+        let null = DecodeOptions::new();
+        let error = DecodeOptions::new().undefined(UndefinedMode::Error);
+        let preserve = DecodeOptions::new().undefined(UndefinedMode::Preserve);
+
+        assert_eq!(null.decode::<Ipld>(&[0xf7]).unwrap(), Ipld::Null);
+        assert_eq!(null.decode::<Option<Undefined>>(&[0xf7]).unwrap(), None);
+        for options in [error, preserve] {
+            let err = options.decode::<Ipld>(&[0x81, 0xf7]).unwrap_err();
+            assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0xf7);
+        }
+        let err = error.decode::<Option<u8>>(&[0xf7]).unwrap_err();
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0xf7);
+        assert_eq!(
+            preserve.decode::<Option<Undefined>>(&[0xf7]).unwrap(),
+            Some(Undefined)
+        );
+        assert_eq!(preserve.decode::<Option<Undefined>>(&[0xf6]).unwrap(), None);
+
+        // the options only apply within `decode`
+        assert_eq!(RawCborCodec.decode::<Ipld>(&[0xf7]).unwrap(), Ipld::Null);
+    }
+
+    #[test]
+    fn numbers() {
+        let lenient = DecodeOptions::new().numbers(NumberMode::Lenient);
+        let strict = DecodeOptions::new().numbers(NumberMode::Strict);
+
+        // 1.0 as a double, 1 with a two byte head, -1, 2^24 + 1 and 1.5
+        let double = [0xfb, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0];
+        let long = [0x19, 0x00, 0x01];
+        let odd = [0x1a, 0x01, 0x00, 0x00, 0x01];
+        let half = [0xf9, 0x3e, 0x00];
+
+        // long heads and narrowing floats only in lenient mode
+        for options in [DecodeOptions::new(), strict] {
+            let err = options.decode::<f32>(&double).unwrap_err();
+            assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0xfb);
+            let err = options.decode::<u8>(&long).unwrap_err();
+            assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0x19);
+        }
+        assert_eq!(lenient.decode::<f32>(&double).unwrap(), 1.0);
+        assert_eq!(lenient.decode::<u8>(&long).unwrap(), 1);
+        assert_eq!(lenient.decode::<f32>(&half).unwrap(), 1.5);
+        assert!(RawCborCodec.decode::<f64>(&[0x01]).is_err());
+        assert!(RawCborCodec.decode::<u8>(&double).is_err());
+        assert!(RawCborCodec.decode::<i8>(&[0x01]).is_err());
+
+        assert_eq!(lenient.decode::<f64>(&[0x01]).unwrap(), 1.0);
+        assert_eq!(lenient.decode::<f32>(&[0x20]).unwrap(), -1.0);
+        assert_eq!(lenient.decode::<f64>(&odd).unwrap(), 16_777_217.0);
+        assert_eq!(lenient.decode::<u8>(&double).unwrap(), 1);
+        assert_eq!(lenient.decode::<i8>(&long).unwrap(), 1);
+        assert_eq!(lenient.decode::<i64>(&[0x20]).unwrap(), -1);
+        // conversions that would lose information
+        let err = lenient.decode::<f32>(&odd).unwrap_err();
+        err.downcast::<NumberOutOfRange>().unwrap();
+        let err = lenient.decode::<u8>(&half).unwrap_err();
+        err.downcast::<UnexpectedCode>().unwrap();
+        let err = lenient.decode::<u8>(&[0x20]).unwrap_err();
+        err.downcast::<NumberOutOfRange>().unwrap();
+        let max = [0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let err = lenient.decode::<f64>(&max).unwrap_err();
+        err.downcast::<NumberOutOfRange>().unwrap();
+        assert!(lenient.decode::<u8>(&[0x61, 0x31]).is_err());
+
+        for bytes in [&double[..], &long, &[0xfa, 0x7f, 0x80, 0, 0]] {
+            let err = strict.decode::<f64>(bytes).unwrap_err();
+            err.downcast::<UnexpectedCode>().unwrap();
+        }
+        assert_eq!(strict.decode::<u16>(&[0x19, 0x01, 0x00]).unwrap(), 256);
+        assert_eq!(RawCborCodec.decode::<u16>(&[0x19, 0x00, 0x01]).unwrap(), 1);
+        assert!(strict.decode::<u16>(&[0x19, 0x00, 0x01]).is_err());
+        assert!(strict.decode::<f64>(&half).is_err());
+        let bytes = RawCborCodec.encode(&vec![1.1f64, 1.5, f64::NAN]).unwrap();
+        assert_eq!(strict.decode::<Vec<f64>>(&bytes).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,245 @@
+//! Partial decoding of a single item addressed by a path.
+use crate::{
+    decode::{read_bytes, read_u8},
+    error::Location,
+    io::{Read, Reader, Seek, SliceReader},
+    token::{read_token, to_usize, Token},
+    unshared, RawCborCodec,
+};
+use alloc::{boxed::Box, string::String};
+use core::fmt;
+use libipld_core::{
+    codec::{Codec, Decode, Encode},
+    error::Result,
+    raw_value::SkipOne,
+};
+
+/// A single step of a path into a cbor item.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PathSegment {
+    /// A map key in its cbor encoded form.
+    ///
+    /// Keys are compared byte by byte, so a key only matches if it is encoded the same way in the
+    /// data, e.g. with the same head size.
+    Key(Box<[u8]>),
+    /// An array index.
+    Index(usize),
+}
+
+impl PathSegment {
+    /// Creates a map key segment from any encodable key.
+    pub fn key<K: Encode<RawCborCodec> + ?Sized>(key: &K) -> Result<Self> {
+        Ok(Self::Key(RawCborCodec.encode(key)?.into_boxed_slice()))
+    }
+
+    /// Creates an array index segment.
+    pub fn index(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+/// Shows indices as `[0]`, text keys as `["key"]` and other keys as their encoding in hex, e.g.
+/// `[<8201>]`.
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "[{}]", index),
+            Self::Key(key) => match RawCborCodec.decode::<String>(key) {
+                Ok(key) => write!(f, "[{:?}]", key),
+                Err(_) => {
+                    f.write_str("[<")?;
+                    for byte in key.iter() {
+                        write!(f, "{:02x}", byte)?;
+                    }
+                    f.write_str(">]")
+                }
+            },
+        }
+    }
+}
+
+impl From<usize> for PathSegment {
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+impl From<&str> for PathSegment {
+    fn from(key: &str) -> Self {
+        // encoding a str into a vec can not fail
+        Self::key(key).expect("infallible")
+    }
+}
+
+impl From<String> for PathSegment {
+    fn from(key: String) -> Self {
+        Self::from(key.as_str())
+    }
+}
+
+/// Advances to the next item of an array or map with `len` items, or of an indefinite length
+/// array or map if `len` is `None`.
+///
+/// Returns `false` if there are no more items, in which case the break byte has been consumed.
+pub(crate) fn next_item<R: Read + Seek>(r: &mut R, len: &mut Option<usize>) -> Result<bool> {
+    match len {
+        Some(0) => Ok(false),
+        Some(n) => {
+            *n -= 1;
+            Ok(true)
+        }
+        None => {
+            if read_u8(r)? == 0xff {
+                return Ok(false);
+            }
+            r.advance(-1)?;
+            Ok(true)
+        }
+    }
+}
+
+/// Advances `r` from the start of an array to the start of the element at `index`.
+pub(crate) fn seek_index<R: Read + Seek>(r: &mut R, index: usize) -> Result<bool> {
+    let mut len = match read_token(r)? {
+        Token::ArrayStart(len) => len.map(to_usize).transpose()?,
+        _ => return Ok(false),
+    };
+    let mut i = 0;
+    while next_item(r, &mut len)? {
+        if i == index {
+            return Ok(true);
+        }
+        RawCborCodec.skip(r)?;
+        i += 1;
+    }
+    Ok(false)
+}
+
+/// Advances `r` from the start of a map to the start of the value for the encoded `key`.
+pub(crate) fn seek_key<R: Read + Seek>(r: &mut R, key: &[u8]) -> Result<bool> {
+    let mut len = match read_token(r)? {
+        Token::MapStart(len) => len.map(to_usize).transpose()?,
+        _ => return Ok(false),
+    };
+    while next_item(r, &mut len)? {
+        let p0 = r.position()?;
+        RawCborCodec.skip(r)?;
+        let p1 = r.position()?;
+        if p1 - p0 == key.len() as u64 {
+            r.advance(p0 as i64 - p1 as i64)?;
+            let found = read_bytes(r, key.len())? == key;
+            if found {
+                return Ok(true);
+            }
+        }
+        RawCborCodec.skip(r)?;
+    }
+    Ok(false)
+}
+
+/// Advances `r` from the start of an item to the start of the item addressed by `path`, skipping
+/// over all unrelated items.
+///
+/// Returns `false` if the path does not exist, in which case the position of `r` is unspecified.
+/// A path does not exist if a key is missing, an index is out of bounds, or an intermediate item
+/// is not of the expected kind.
+pub fn seek_path<R: Read + Seek>(r: &mut R, path: &[PathSegment]) -> Result<bool> {
+    for segment in path {
+        let found = match segment {
+            PathSegment::Index(index) => seek_index(r, *index)?,
+            PathSegment::Key(key) => seek_key(r, key)?,
+        };
+        if !found {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Decodes only the item addressed by `path` from a cbor encoded byte slice.
+///
+/// Returns `None` if the path does not exist. Like [`Codec::decode`], errors carry a
+/// [`Location`] relative to the start of `bytes`.
+pub fn decode_path<T: Decode<RawCborCodec>>(
+    bytes: &[u8],
+    path: &[PathSegment],
+) -> Result<Option<T>> {
+    let mut r = SliceReader::new(bytes);
+    if !seek_path(&mut r, path).map_err(|err| Location::attach(err, 0))? {
+        return Ok(None);
+    }
+    let offset = r.position()?;
+    unshared(|| T::decode(RawCborCodec, &mut r))
+        .map(Some)
+        .map_err(|err| Location::attach(err, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::ipld::Ipld;
+    use libipld_macro::ipld;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn string_keys_and_indices() {
+        let ipld = ipld!({
+            "a": [1, 2, { "b": "hello" }],
+            "c": true,
+        });
+        let bytes = RawCborCodec.encode(&ipld).unwrap();
+        let path = ["a".into(), 2.into(), "b".into()];
+        let value: Option<String> = decode_path(&bytes, &path).unwrap();
+        assert_eq!(value.as_deref(), Some("hello"));
+        let value: Option<bool> = decode_path(&bytes, &["c".into()]).unwrap();
+        assert_eq!(value, Some(true));
+        let value: Option<Ipld> = decode_path(&bytes, &[]).unwrap();
+        assert_eq!(value, Some(ipld));
+    }
+
+    #[test]
+    fn missing() {
+        let ipld = ipld!({ "a": [1, 2] });
+        let bytes = RawCborCodec.encode(&ipld).unwrap();
+        for path in [
+            vec!["b".into()],
+            vec!["a".into(), 2.into()],
+            vec!["a".into(), "b".into()],
+            vec!["a".into(), 0.into(), 0.into()],
+        ] {
+            let value: Option<Ipld> = decode_path(&bytes, &path).unwrap();
+            assert_eq!(value, None);
+        }
+    }
+
+    #[test]
+    fn non_string_keys() {
+        let mut map = BTreeMap::new();
+        map.insert((1u8, 2u8), "x".to_string());
+        map.insert((3u8, 4u8), "y".to_string());
+        let bytes = RawCborCodec.encode(&map).unwrap();
+        let path = [PathSegment::key(&(3u8, 4u8)).unwrap()];
+        let value: Option<String> = decode_path(&bytes, &path).unwrap();
+        assert_eq!(value.as_deref(), Some("y"));
+    }
+
+    #[test]
+    fn indefinite_length() {
+        let bytes = [
+            0xbf, // Start indefinite-length map
+            0x61, 0x61, // "a"
+            0x9f, 0x01, 0x02, 0xff, // [_ 1, 2]
+            0x61, 0x62, // "b"
+            0x03, // 3
+            0xff, // "break"
+        ];
+        let value: Option<u8> = decode_path(&bytes, &["a".into(), 1.into()]).unwrap();
+        assert_eq!(value, Some(2));
+        let value: Option<u8> = decode_path(&bytes, &["b".into()]).unwrap();
+        assert_eq!(value, Some(3));
+        let value: Option<u8> = decode_path(&bytes, &["a".into(), 2.into()]).unwrap();
+        assert_eq!(value, None);
+        let err = decode_path::<String>(&bytes, &["b".into()]).unwrap_err();
+        assert_eq!(err.downcast_ref::<Location>().unwrap().offset, 9);
+    }
+}
@@ -0,0 +1,89 @@
+//! Self-described cbor (RFC 8949, section 3.4.6).
+//!
+//! Tag 55799 has no meaning of its own. Prefixing an item with it makes the encoding start with
+//! the magic bytes `d9 d9 f7`, which identify cbor files.
+use crate::{
+    decode::read_u8,
+    encode::write_tag,
+    encoded_len::EncodedLen,
+    io::{Read, Reader, Seek, Write},
+    token::{read_token_from, Token},
+    RawCborCodec,
+};
+use libipld_core::{
+    codec::{Decode, Encode},
+    error::Result,
+};
+
+/// The self-describe tag.
+pub const SELF_DESCRIBE: u64 = 55799;
+
+/// The encoding of the self-describe tag, which cbor files start with.
+pub const MAGIC: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+/// A value encoded with the self-describe tag in front.
+///
+/// Decoding accepts the value with or without the tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SelfDescribed<T>(pub T);
+
+impl<T: Encode<RawCborCodec>> Encode<RawCborCodec> for SelfDescribed<T> {
+    fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
+        write_tag(w, SELF_DESCRIBE)?;
+        self.0.encode(c, w)
+    }
+}
+
+impl<T: Decode<RawCborCodec>> Decode<RawCborCodec> for SelfDescribed<T> {
+    fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
+        let start = r.position()?;
+        let major = read_u8(r)?;
+        if read_token_from(r, major)? != Token::Tag(SELF_DESCRIBE) {
+            let end = r.position()?;
+            r.advance(start as i64 - end as i64)?;
+        }
+        Ok(Self(T::decode(c, r)?))
+    }
+}
+
+impl<T: EncodedLen> EncodedLen for SelfDescribed<T> {
+    fn encoded_len(&self) -> usize {
+        MAGIC.len() + self.0.encoded_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::{codec::Codec, ipld::Ipld, raw_value::SkipOne};
+
+    #[test]
+    fn self_described() {
+        let value = SelfDescribed(vec![1u8, 2]);
+        let bytes = RawCborCodec.encode(&value).unwrap();
+        assert_eq!(bytes, [0xd9, 0xd9, 0xf7, 0x82, 0x01, 0x02]);
+        assert!(bytes.starts_with(&MAGIC));
+        assert_eq!(value.encoded_len(), bytes.len());
+        assert_eq!(
+            RawCborCodec
+                .decode::<SelfDescribed<Vec<u8>>>(&bytes)
+                .unwrap(),
+            value
+        );
+        assert_eq!(
+            RawCborCodec
+                .decode::<SelfDescribed<Vec<u8>>>(&bytes[3..])
+                .unwrap(),
+            value
+        );
+
+        // ipld and skipping see through the tag
+        assert_eq!(
+            RawCborCodec.decode::<Ipld>(&bytes).unwrap(),
+            Ipld::List(vec![Ipld::Integer(1), Ipld::Integer(2)])
+        );
+        let mut r = crate::io::Cursor::new(&bytes[..]);
+        RawCborCodec.skip(&mut r).unwrap();
+        assert_eq!(r.position(), bytes.len() as u64);
+    }
+}
@@ -0,0 +1,260 @@
+//! Value sharing, the [value-sharing](http://cbor.schmorp.de/value-sharing) extension.
+//!
+//! Within [`encode`], the first occurrence of every `Arc` is written as tag 28 followed by its
+//! value, and later occurrences of the same `Arc` as tag 29 followed by the index of the first
+//! one, counting tag 28 items in the order they start. Within [`decode`], an `Arc` read from such
+//! a reference is a clone of the `Arc` decoded for the tagged value, so the sharing survives the
+//! roundtrip.
+//!
+//! Outside of these functions `Arc`s are encoded and decoded as their values.
+use crate::{
+    decode::read_u8,
+    encode::{write_tag, write_u64},
+    error::{InvalidSharedRef, Location, UnexpectedCode},
+    io::{Read, Reader, Seek, SliceReader, Write},
+    token::{read_token_from, Token},
+    RawCborCodec,
+};
+use libipld_core::{
+    codec::{Decode, Encode},
+    error::Result,
+};
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    thread::LocalKey,
+};
+
+/// Tag of a value that may be referenced later.
+pub const SHAREABLE: u64 = 28;
+
+/// Tag of a reference to a shareable value.
+pub const SHAREDREF: u64 = 29;
+
+/// The `Arc`s decoded so far, `None` while they are still being decoded.
+type Decoded = Vec<Option<Box<dyn Any>>>;
+
+thread_local! {
+    /// The indices of the `Arc`s encoded so far, keyed by address.
+    static ENCODER: RefCell<Option<HashMap<usize, u64>>> = const { RefCell::new(None) };
+    /// The `Arc`s decoded so far by the innermost [`decode`] call.
+    static DECODER: RefCell<Option<Decoded>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with `key` set to `state`, restoring the previous state afterwards, so that nested
+/// calls don't share their tables.
+fn scoped<S: 'static, O>(
+    key: &'static LocalKey<RefCell<Option<S>>>,
+    state: Option<S>,
+    f: impl FnOnce() -> O,
+) -> O {
+    struct Restore<S: 'static>(&'static LocalKey<RefCell<Option<S>>>, Option<S>);
+
+    impl<S: 'static> Drop for Restore<S> {
+        fn drop(&mut self) {
+            let prev = self.1.take();
+            self.0.with(|state| *state.borrow_mut() = prev);
+        }
+    }
+
+    let _restore = Restore(key, key.with(|s| s.replace(state)));
+    f()
+}
+
+/// Encodes an `Arc`, sharing it if called within [`encode`].
+pub(crate) fn encode_arc<T: Encode<RawCborCodec>, W: Write>(
+    value: &Arc<T>,
+    c: RawCborCodec,
+    w: &mut W,
+) -> Result<()> {
+    let address = Arc::as_ptr(value) as usize;
+    let shared = ENCODER.with(|indices| {
+        let mut indices = indices.borrow_mut();
+        let indices = indices.as_mut()?;
+        let next = indices.len() as u64;
+        match indices.entry(address) {
+            Entry::Occupied(entry) => Some(Some(*entry.get())),
+            Entry::Vacant(entry) => {
+                entry.insert(next);
+                Some(None)
+            }
+        }
+    });
+    match shared {
+        None => (**value).encode(c, w),
+        Some(None) => {
+            write_tag(w, SHAREABLE)?;
+            (**value).encode(c, w)
+        }
+        Some(Some(index)) => {
+            write_tag(w, SHAREDREF)?;
+            write_u64(w, 0, index)
+        }
+    }
+}
+
+/// Decodes an `Arc`, resolving shared values if called within [`decode`].
+pub(crate) fn decode_arc<T: Decode<RawCborCodec> + 'static, R: Read + Seek>(
+    c: RawCborCodec,
+    r: &mut R,
+) -> Result<Arc<T>> {
+    if DECODER.with(|values| values.borrow().is_none()) {
+        return Ok(Arc::new(T::decode(c, r)?));
+    }
+    let start = r.position()?;
+    let major = read_u8(r)?;
+    match read_token_from(r, major)? {
+        Token::Tag(SHAREABLE) => {
+            let index = DECODER.with(|values| {
+                let mut values = values.borrow_mut();
+                let values = values.as_mut()?;
+                values.push(None);
+                Some(values.len() - 1)
+            });
+            let value = Arc::new(T::decode(c, r)?);
+            DECODER.with(|values| {
+                if let (Some(values), Some(index)) = (values.borrow_mut().as_mut(), index) {
+                    values[index] = Some(Box::new(value.clone()));
+                }
+            });
+            Ok(value)
+        }
+        Token::Tag(SHAREDREF) => {
+            let major = read_u8(r)?;
+            let index = match read_token_from(r, major)? {
+                Token::UInt(index) => index,
+                _ => return Err(UnexpectedCode::new::<Arc<T>>(major).into()),
+            };
+            // an index that is out of range, still being decoded or of another type
+            let value = DECODER.with(|values| {
+                let values = values.borrow();
+                let value = values.as_ref()?.get(usize::try_from(index).ok()?)?;
+                value.as_ref()?.downcast_ref::<Arc<T>>().cloned()
+            });
+            value.ok_or_else(|| InvalidSharedRef(index).into())
+        }
+        _ => {
+            let end = r.position()?;
+            r.advance(start as i64 - end as i64)?;
+            Ok(Arc::new(T::decode(c, r)?))
+        }
+    }
+}
+
+/// Encodes `value`, writing every distinct `Arc` it contains once.
+///
+/// `Arc`s are identified by address, so equal values in different `Arc`s are written separately.
+pub fn encode<T: Encode<RawCborCodec> + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    scoped(&ENCODER, Some(HashMap::new()), || {
+        value.encode(RawCborCodec, &mut buf)
+    })?;
+    Ok(buf)
+}
+
+/// Decodes `bytes`, returning clones of the same `Arc` for a shared value and its references.
+///
+/// Untagged values decode into separate `Arc`s as usual. Fails with [`InvalidSharedRef`] if a
+/// reference points to a value that doesn't exist yet or that was decoded as another type.
+pub fn decode<T: Decode<RawCborCodec>>(bytes: &[u8]) -> Result<T> {
+    scoped(&DECODER, Some(Vec::new()), || {
+        T::decode(RawCborCodec, &mut SliceReader::new(bytes))
+    })
+    .map_err(|err| Location::attach(err, 0))
+}
+
+/// Runs `f` outside of [`encode`] and [`decode`], so that nested calls of the codec, such as
+/// encoding a block within a shared value, don't share the tables of the outer call.
+pub(crate) fn unshared<O>(f: impl FnOnce() -> O) -> O {
+    scoped(&ENCODER, None, || scoped(&DECODER, None, f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::UnknownTag;
+    use libipld_core::{codec::Codec, ipld::Ipld};
+
+    #[test]
+    fn shared_arcs() {
+        let leaf = Arc::new("leaf".to_string());
+        let node = Arc::new(vec![leaf.clone(), leaf.clone()]);
+        let tree = vec![node.clone(), node, Arc::new(vec![leaf])];
+        let bytes = encode(&tree).unwrap();
+        // [28([28("leaf"), 29(1)]), 29(0), 28([29(1)])]
+        assert_eq!(
+            hex::encode(&bytes),
+            "83d81c82d81c646c656166d81d01d81d00d81c81d81d01"
+        );
+        let decoded: Vec<Arc<Vec<Arc<String>>>> = decode(&bytes).unwrap();
+        assert_eq!(decoded, tree);
+        assert!(Arc::ptr_eq(&decoded[0], &decoded[1]));
+        assert!(!Arc::ptr_eq(&decoded[0], &decoded[2]));
+        assert!(Arc::ptr_eq(&decoded[0][0], &decoded[2][0]));
+
+        // plain encoding and decoding are unchanged
+        let plain = RawCborCodec.encode(&tree).unwrap();
+        assert_eq!(
+            hex::encode(&plain),
+            "8382646c656166646c65616682646c656166646c65616681646c656166"
+        );
+        let decoded: Vec<Arc<Vec<Arc<String>>>> = decode(&plain).unwrap();
+        assert_eq!(decoded, tree);
+        assert!(!Arc::ptr_eq(&decoded[0], &decoded[1]));
+        let err = RawCborCodec.decode::<Ipld>(&bytes).unwrap_err();
+        assert_eq!(err.downcast::<UnknownTag>().unwrap().0, SHAREABLE);
+    }
+
+    #[test]
+    fn invalid() {
+        for hex in [
+            // out of range
+            "81d81d00",
+            // a reference to a value that is still being decoded
+            "81d81c81d81d00",
+        ] {
+            let err = decode::<Vec<Arc<Vec<Arc<u8>>>>>(&hex::decode(hex).unwrap()).unwrap_err();
+            assert_eq!(err.downcast::<InvalidSharedRef>().unwrap().0, 0);
+        }
+        // a reference to a value of another type
+        let err =
+            decode::<(Arc<u64>, Arc<String>)>(&hex::decode("82d81c01d81d00").unwrap()).unwrap_err();
+        assert_eq!(err.downcast::<InvalidSharedRef>().unwrap().0, 0);
+    }
+
+    /// Encodes its `Arc` with a nested call of the codec, like a block.
+    #[derive(Debug)]
+    struct Nested(Arc<String>);
+
+    impl Encode<RawCborCodec> for Nested {
+        fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
+            Ipld::Bytes(RawCborCodec.encode(&self.0)?).encode(c, w)
+        }
+    }
+
+    impl Decode<RawCborCodec> for Nested {
+        fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
+            match Ipld::decode(c, r)? {
+                Ipld::Bytes(bytes) => Ok(Self(RawCborCodec.decode(&bytes)?)),
+                _ => Err(UnexpectedCode::new::<Self>(0).into()),
+            }
+        }
+    }
+
+    #[test]
+    fn nested() {
+        let leaf = Arc::new("leaf".to_string());
+        let bytes = encode(&(leaf.clone(), Nested(leaf))).unwrap();
+        // [28("leaf"), h'646c656166']
+        assert_eq!(hex::encode(&bytes), "82d81c646c65616645646c656166");
+        let (leaf, nested) = decode::<(Arc<String>, Nested)>(&bytes).unwrap();
+        assert_eq!(leaf, nested.0);
+
+        // [28("leaf"), h'd81d00'] doesn't resolve the nested reference
+        let bytes = hex::decode("82d81c646c65616643d81d00").unwrap();
+        let err = decode::<(Arc<String>, Nested)>(&bytes).unwrap_err();
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0xd8);
+    }
+}
@@ -0,0 +1,140 @@
+//! Simple values (major type 7) other than floats.
+use crate::{
+    decode::read_u8,
+    encoded_len::EncodedLen,
+    error::UnexpectedCode,
+    io::{Read, Seek, Write, Writer},
+    token::{read_token_from, Token},
+    RawCborCodec,
+};
+use libipld_core::{
+    codec::{Decode, Encode},
+    error::Result,
+};
+
+/// A simple value, e.g. 20 and 21 for false and true or one of the unassigned values.
+///
+/// The values 24 to 31 are reserved and can't be constructed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Simple(u8);
+
+impl Simple {
+    /// False.
+    pub const FALSE: Self = Self(20);
+    /// True.
+    pub const TRUE: Self = Self(21);
+    /// Null.
+    pub const NULL: Self = Self(22);
+    /// Undefined.
+    pub const UNDEFINED: Self = Self(23);
+
+    /// Creates a simple value, or returns `None` for the reserved values 24 to 31.
+    pub fn new(value: u8) -> Option<Self> {
+        match value {
+            24..=31 => None,
+            _ => Some(Self(value)),
+        }
+    }
+
+    /// The number of the simple value.
+    pub fn value(self) -> u8 {
+        self.0
+    }
+}
+
+impl Encode<RawCborCodec> for Simple {
+    fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
+        if self.0 < 24 {
+            Writer::write_all(w, &[0xe0 | self.0])
+        } else {
+            Writer::write_all(w, &[0xf8, self.0])
+        }
+    }
+}
+
+impl Decode<RawCborCodec> for Simple {
+    fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
+        let major = read_u8(r)?;
+        match read_token_from(r, major)? {
+            Token::Simple(value) => Ok(Self(value)),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
+    }
+}
+
+impl EncodedLen for Simple {
+    fn encoded_len(&self) -> usize {
+        if self.0 < 24 {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+/// The undefined value, which unlike `None` doesn't encode as null.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Undefined;
+
+impl Encode<RawCborCodec> for Undefined {
+    fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
+        Simple::UNDEFINED.encode(c, w)
+    }
+}
+
+impl Decode<RawCborCodec> for Undefined {
+    fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
+        let major = read_u8(r)?;
+        match major {
+            0xf7 => Ok(Self),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
+    }
+}
+
+impl EncodedLen for Undefined {
+    fn encoded_len(&self) -> usize {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::codec::Codec;
+
+    #[test]
+    fn simple() {
+        for (value, hex) in [
+            (0, "e0"),
+            (16, "f0"),
+            (23, "f7"),
+            (32, "f820"),
+            (255, "f8ff"),
+        ] {
+            let simple = Simple::new(value).unwrap();
+            let bytes = RawCborCodec.encode(&simple).unwrap();
+            assert_eq!(hex::encode(&bytes), hex);
+            assert_eq!(simple.encoded_len(), bytes.len());
+            assert_eq!(RawCborCodec.decode::<Simple>(&bytes).unwrap(), simple);
+        }
+        assert!(Simple::new(24).is_none());
+        assert!(Simple::new(31).is_none());
+        assert_eq!(
+            RawCborCodec.decode::<Simple>(&[0xf5]).unwrap(),
+            Simple::TRUE
+        );
+        // floats and two byte encodings of small values are not simple values
+        assert!(RawCborCodec.decode::<Simple>(&[0xf9, 0, 0]).is_err());
+        assert!(RawCborCodec.decode::<Simple>(&[0xf8, 0x10]).is_err());
+    }
+
+    #[test]
+    fn undefined() {
+        let bytes = RawCborCodec.encode(&Undefined).unwrap();
+        assert_eq!(bytes, [0xf7]);
+        assert_eq!(RawCborCodec.decode::<Undefined>(&bytes).unwrap(), Undefined);
+        let err = RawCborCodec.decode::<Undefined>(&[0xf6]).unwrap_err();
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0xf6);
+    }
+}
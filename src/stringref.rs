@@ -0,0 +1,364 @@
+//! String references, the [stringref](http://cbor.schmorp.de/stringref) extension.
+//!
+//! Inside a namespace started by tag 256, every definite length string long enough to benefit is
+//! assigned the next index, and a later occurrence of the same string can be written as tag 25
+//! followed by that index. Repeated map keys then take two to three bytes each.
+//!
+//! [`pack`] and [`unpack`] convert between plain and packed items without decoding them, and
+//! [`encode`] and [`decode`] combine them with the codec. `Decode for Ipld` also resolves
+//! namespaces by itself, so packed blocks can be decoded into `Ipld` like any other block.
+use crate::{
+    decode::{read_bytes, read_head, read_u8},
+    encode::{write_tag, write_u64},
+    error::{InvalidStringRef, Location, UnexpectedCode, UnknownTag},
+    io::{Read, Reader, Seek, SliceReader, Writer},
+    token::{items, read_token_from, to_usize, Token},
+    RawCborCodec,
+};
+use alloc::{collections::BTreeMap, vec::Vec};
+use libipld_core::{
+    codec::{Codec, Decode, Encode},
+    error::Result,
+    ipld::Ipld,
+};
+
+/// Tag of a string reference.
+pub const STRINGREF: u64 = 25;
+
+/// Tag starting a stringref namespace.
+pub const NAMESPACE: u64 = 256;
+
+/// The minimum length of a string that is assigned `index`, so that a reference is shorter.
+fn min_len(index: u64) -> usize {
+    match index {
+        0..=23 => 3,
+        24..=0xff => 4,
+        0x100..=0xffff => 5,
+        0x1_0000..=0xffff_ffff => 7,
+        _ => 11,
+    }
+}
+
+/// The strings of a namespace while packing, keyed by major type and bytes.
+#[derive(Default)]
+struct Strings {
+    indices: BTreeMap<(u8, Vec<u8>), u64>,
+    len: u64,
+}
+
+impl Strings {
+    /// Assigns the next index to a string written literally, if it is long enough.
+    ///
+    /// A string that is already known still takes up an index, since decoders don't deduplicate.
+    fn add(&mut self, major: u8, bytes: Vec<u8>) {
+        if bytes.len() >= min_len(self.len) {
+            self.indices.entry((major, bytes)).or_insert(self.len);
+            self.len += 1;
+        }
+    }
+}
+
+/// Copies the chunks of an indefinite length string, including the break code.
+///
+/// Chunks are never assigned an index.
+fn copy_chunks<R: Reader + ?Sized, W: Writer + ?Sized>(
+    r: &mut R,
+    w: &mut W,
+    major: u8,
+) -> Result<()> {
+    loop {
+        let mut head = [0; 9];
+        let (len, arg) = read_head(r, &mut head)?;
+        w.write_all(&head[..len])?;
+        match arg {
+            None if head[0] == 0xff => return Ok(()),
+            Some(n) if head[0] >> 5 == major >> 5 => w.write_all(&read_bytes(r, to_usize(n)?)?)?,
+            _ => return Err(UnexpectedCode::new::<Ipld>(head[0]).into()),
+        }
+    }
+}
+
+fn pack_item<R: Reader + ?Sized, W: Writer + ?Sized>(
+    r: &mut R,
+    w: &mut W,
+    strings: &mut Strings,
+) -> Result<()> {
+    let mut head = [0; 9];
+    let (len, arg) = read_head(r, &mut head)?;
+    let major = head[0];
+    match (major >> 5, arg) {
+        (2 | 3, Some(n)) => {
+            let bytes = read_bytes(r, to_usize(n)?)?;
+            if let Some(index) = strings.indices.get(&(major >> 5, bytes.clone())) {
+                write_tag(w, STRINGREF)?;
+                return write_u64(w, 0, *index);
+            }
+            w.write_all(&head[..len])?;
+            w.write_all(&bytes)?;
+            strings.add(major >> 5, bytes);
+        }
+        (2 | 3, None) => {
+            w.write_all(&head[..len])?;
+            copy_chunks(r, w, major)?;
+        }
+        (4 | 5, n) => {
+            w.write_all(&head[..len])?;
+            let width = if major >> 5 == 4 { 1 } else { 2 };
+            items(r, n, |r, _| {
+                for _ in 0..width {
+                    pack_item(r, w, strings)?;
+                }
+                Ok(())
+            })?;
+            if n.is_none() {
+                w.write_all(&[0xff])?;
+            }
+        }
+        // items that already use string references can't be packed again
+        (6, Some(tag @ (STRINGREF | NAMESPACE))) => return Err(UnknownTag(tag).into()),
+        // links are kept as they are, so their references can be read without unpacking
+        (6, Some(42)) => {
+            w.write_all(&head[..len])?;
+            let (len, arg) = read_head(r, &mut head)?;
+            match arg {
+                Some(n) if head[0] >> 5 == 2 => {
+                    let bytes = read_bytes(r, to_usize(n)?)?;
+                    w.write_all(&head[..len])?;
+                    w.write_all(&bytes)?;
+                    strings.add(2, bytes);
+                }
+                _ => return Err(UnexpectedCode::new::<Ipld>(head[0]).into()),
+            }
+        }
+        (6, _) => {
+            w.write_all(&head[..len])?;
+            pack_item(r, w, strings)?;
+        }
+        (7, None) => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+        _ => w.write_all(&head[..len])?,
+    }
+    Ok(())
+}
+
+fn unpack_item<R: Reader + ?Sized, W: Writer + ?Sized>(
+    r: &mut R,
+    w: &mut W,
+    namespaces: &mut Vec<Vec<(u8, Vec<u8>)>>,
+) -> Result<()> {
+    let mut head = [0; 9];
+    let (len, arg) = read_head(r, &mut head)?;
+    let major = head[0];
+    match (major >> 5, arg) {
+        (2 | 3, Some(n)) => {
+            let bytes = read_bytes(r, to_usize(n)?)?;
+            w.write_all(&head[..len])?;
+            w.write_all(&bytes)?;
+            if let Some(strings) = namespaces.last_mut() {
+                if bytes.len() >= min_len(strings.len() as u64) {
+                    strings.push((major >> 5, bytes));
+                }
+            }
+        }
+        (2 | 3, None) => {
+            w.write_all(&head[..len])?;
+            copy_chunks(r, w, major)?;
+        }
+        (4 | 5, n) => {
+            w.write_all(&head[..len])?;
+            let width = if major >> 5 == 4 { 1 } else { 2 };
+            items(r, n, |r, _| {
+                for _ in 0..width {
+                    unpack_item(r, w, namespaces)?;
+                }
+                Ok(())
+            })?;
+            if n.is_none() {
+                w.write_all(&[0xff])?;
+            }
+        }
+        (6, Some(NAMESPACE)) => {
+            namespaces.push(Vec::new());
+            unpack_item(r, w, namespaces)?;
+            namespaces.pop();
+        }
+        (6, Some(STRINGREF)) => {
+            let major = read_u8(r)?;
+            let index = match read_token_from(r, major)? {
+                Token::UInt(index) => index,
+                _ => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+            };
+            let (major, bytes) = namespaces
+                .last()
+                .and_then(|strings| strings.get(usize::try_from(index).ok()?))
+                .ok_or(InvalidStringRef(index))?;
+            write_u64(w, *major, bytes.len() as u64)?;
+            w.write_all(bytes)?;
+        }
+        (6, _) => {
+            w.write_all(&head[..len])?;
+            unpack_item(r, w, namespaces)?;
+        }
+        (7, None) => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+        _ => w.write_all(&head[..len])?,
+    }
+    Ok(())
+}
+
+/// Decodes the item of a namespace whose tag 256 has just been read from `r`.
+///
+/// The item is decoded from a copy with its references resolved, so errors within it are located
+/// at the start of the item.
+pub(crate) fn decode_namespace<T, R>(c: RawCborCodec, r: &mut R) -> Result<T>
+where
+    T: Decode<RawCborCodec>,
+    R: Read + Seek,
+{
+    let offset = r.position()?;
+    let mut bytes = Vec::new();
+    unpack_item(r, &mut bytes, &mut Vec::from([Vec::new()]))?;
+    T::decode(c, &mut SliceReader::new(&bytes)).map_err(|mut err| {
+        if let Some(location) = err.downcast_mut::<Location>() {
+            location.offset = offset;
+        }
+        err
+    })
+}
+
+/// Packs a single encoded item into a stringref namespace, replacing repeated strings with
+/// references.
+///
+/// Heads, floats and the order of map keys are copied verbatim. Fails with [`UnknownTag`] if the
+/// item already contains tag 25 or 256.
+pub fn pack(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(bytes.len());
+    write_tag(&mut out, NAMESPACE)?;
+    pack_item(
+        &mut SliceReader::new(bytes),
+        &mut out,
+        &mut Strings::default(),
+    )?;
+    Ok(out)
+}
+
+/// Resolves the string references of a single encoded item and removes its namespace tags.
+///
+/// Fails with [`InvalidStringRef`] if a reference is outside of a namespace or its index has not
+/// been assigned.
+pub fn unpack(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(bytes.len());
+    unpack_item(&mut SliceReader::new(bytes), &mut out, &mut Vec::new())?;
+    Ok(out)
+}
+
+/// Encodes `value` and packs it, see [`pack`].
+pub fn encode<T: Encode<RawCborCodec> + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    pack(&RawCborCodec.encode(value)?)
+}
+
+/// Unpacks `bytes` and decodes them, see [`unpack`]. This is only needed for types other than
+/// `Ipld`.
+///
+/// Decoding locations refer to the unpacked bytes.
+pub fn decode<T: Decode<RawCborCodec>>(bytes: &[u8]) -> Result<T> {
+    RawCborCodec.decode(&unpack(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_macro::ipld;
+
+    #[test]
+    fn repeated_keys() {
+        let record = ipld!({ "timestamp": 1, "temperature": 20.5, "ok": true });
+        let ipld = Ipld::List(vec![record; 100]);
+        let plain = RawCborCodec.encode(&ipld).unwrap();
+        let packed = encode(&ipld).unwrap();
+        assert!(packed.len() * 3 < plain.len() * 2);
+        assert_eq!(unpack(&packed).unwrap(), plain);
+        assert_eq!(decode::<Ipld>(&packed).unwrap(), ipld);
+    }
+
+    #[test]
+    fn indices() {
+        // the example of the specification
+        let strings = [
+            "1", "222", "333", "4", "555", "666", "777", "888", "999", "aaa", "bbb", "ccc", "ddd",
+            "eee", "fff", "ggg", "hhh", "iii", "jjj", "kkk", "lll", "mmm", "nnn", "ooo", "ppp",
+            "qqq", "rrr", "333", "ssss", "qqq", "rrr", "ssss",
+        ];
+        let ipld = Ipld::List(
+            strings
+                .iter()
+                .map(|s| Ipld::String(s.to_string()))
+                .collect(),
+        );
+        let plain = RawCborCodec.encode(&ipld).unwrap();
+        let packed = pack(&plain).unwrap();
+        assert!(packed.starts_with(&hex::decode("d901009820613163323232").unwrap()));
+        // "rrr" is too short for index 24, so "ssss" gets it
+        let tail = hex::decode("63727272d819016473737373d8191763727272d8191818").unwrap();
+        assert!(packed.ends_with(&tail));
+        assert_eq!(unpack(&packed).unwrap(), plain);
+    }
+
+    #[test]
+    fn byte_and_text_strings() {
+        let ipld = ipld!([
+            vec![0x61u8, 0x62, 0x63],
+            "abc",
+            vec![0x61u8, 0x62, 0x63],
+            "abc"
+        ]);
+        let packed = encode(&ipld).unwrap();
+        assert_eq!(hex::encode(&packed), "d90100844361626363616263d81900d81901");
+        assert_eq!(decode::<Ipld>(&packed).unwrap(), ipld);
+    }
+
+    #[test]
+    fn nested_namespaces() {
+        // 256(["abc", 256(["abc", 25(0)]), 25(0)])
+        let packed = hex::decode("d901008363616263d901008263616263d81900d81900").unwrap();
+        let ipld: Ipld = decode(&packed).unwrap();
+        assert_eq!(ipld, ipld!(["abc", ["abc", "abc"], "abc"]));
+        // namespaces don't have to start at the root for the codec
+        let mut wrapped = vec![0x82, 0x01];
+        wrapped.extend_from_slice(&packed);
+        let ipld: Ipld = RawCborCodec.decode(&wrapped).unwrap();
+        assert_eq!(ipld, ipld!([1, ["abc", ["abc", "abc"], "abc"]]));
+    }
+
+    #[test]
+    fn codec() {
+        let ipld = ipld!({ "key": [{ "key": 1 }, { "key": 2 }] });
+        let packed = encode(&ipld).unwrap();
+        assert_eq!(RawCborCodec.decode::<Ipld>(&packed).unwrap(), ipld);
+        // a reference outside of a namespace, and an error inside of one
+        let err = RawCborCodec
+            .decode::<Ipld>(&[0x81, 0xd8, 0x19, 0x00])
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Location>().unwrap().offset, 1);
+        assert_eq!(err.downcast::<InvalidStringRef>().unwrap().0, 0);
+        let err = RawCborCodec
+            .decode::<Ipld>(&hex::decode("8201d9010082636b6579f8ff").unwrap())
+            .unwrap_err();
+        let location = err.downcast_ref::<Location>().unwrap();
+        assert_eq!((location.offset, location.path.len()), (5, 2));
+    }
+
+    #[test]
+    fn invalid() {
+        // a reference outside of a namespace, and to an unassigned index
+        for hex in ["d81900", "d90100d81900", "d9010082626162d81900"] {
+            let err = unpack(&hex::decode(hex).unwrap()).unwrap_err();
+            assert_eq!(err.downcast::<InvalidStringRef>().unwrap().0, 0);
+        }
+        // a reference that is not an unsigned integer
+        let err = unpack(&hex::decode("d90100d81920").unwrap()).unwrap_err();
+        err.downcast::<UnexpectedCode>().unwrap();
+        // packing twice
+        let packed = pack(&[0x60]).unwrap();
+        let err = pack(&packed).unwrap_err();
+        assert_eq!(err.downcast::<UnknownTag>().unwrap().0, NAMESPACE);
+    }
+}
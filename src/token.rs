@@ -0,0 +1,234 @@
+//! Pull tokenizer the decoders are built on.
+//!
+//! Every decoder reads items as a sequence of [`Token`]s, so the meaning of each initial byte is
+//! defined in one place. Only the dag-cbor checker and transcoding, which need the exact bytes of
+//! each head, read heads directly.
+use crate::{
+    decode::{f16_to_f64, read_bytes, read_u16, read_u32, read_u64, read_u8, skip_bytes},
+    error::{LengthOutOfRange, UnexpectedCode},
+    io::Reader,
+};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use libipld_core::{error::Result, ipld::Ipld};
+
+/// The head of a cbor item, decoded into its major type and argument.
+///
+/// The payload of strings and the items of arrays, maps and tags follow the token.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Token {
+    /// Major type 0: an unsigned integer.
+    UInt(u64),
+    /// Major type 1: the negative integer `-1 - n`.
+    NInt(u64),
+    /// Major type 2: a byte string of the given length, followed by its bytes.
+    ///
+    /// An indefinite length byte string has no length and is followed by definite length byte
+    /// strings and a [`Token::Break`].
+    Bytes(Option<u64>),
+    /// Major type 3: a text string, laid out like [`Token::Bytes`].
+    Text(Option<u64>),
+    /// Major type 4: an array with the given number of items, or an indefinite length array
+    /// whose items end with a [`Token::Break`].
+    ArrayStart(Option<u64>),
+    /// Major type 5: a map with the given number of pairs, or an indefinite length map whose
+    /// pairs end with a [`Token::Break`].
+    MapStart(Option<u64>),
+    /// Major type 6: a tag number, followed by the tagged item.
+    Tag(u64),
+    /// Major type 7: a simple value, e.g. 20 and 21 for false and true.
+    Simple(u8),
+    /// Major type 7: a half, single or double precision float.
+    Float(f64),
+    /// The end of an indefinite length string, array or map.
+    Break,
+}
+
+/// Reads the next token.
+pub fn read_token<R: Reader + ?Sized>(r: &mut R) -> Result<Token> {
+    let major = read_u8(r)?;
+    read_token_from(r, major)
+}
+
+/// Reads the rest of a token whose initial byte `major` has already been read.
+///
+/// Fails with [`UnexpectedCode`] for reserved codes, indefinite lengths on major types that
+/// don't allow them and two byte encodings of simple values below 32.
+pub fn read_token_from<R: Reader + ?Sized>(r: &mut R, major: u8) -> Result<Token> {
+    let info = major & 0x1f;
+    let arg = match info {
+        0x00..=0x17 => Some(info as u64),
+        0x18 => Some(read_u8(r)? as u64),
+        0x19 => Some(read_u16(r)? as u64),
+        0x1a => Some(read_u32(r)? as u64),
+        0x1b => Some(read_u64(r)?),
+        0x1f => None,
+        _ => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+    };
+    let token = match (major >> 5, arg) {
+        (0, Some(n)) => Token::UInt(n),
+        (1, Some(n)) => Token::NInt(n),
+        (2, len) => Token::Bytes(len),
+        (3, len) => Token::Text(len),
+        (4, len) => Token::ArrayStart(len),
+        (5, len) => Token::MapStart(len),
+        (6, Some(tag)) => Token::Tag(tag),
+        (7, Some(bits)) => match info {
+            // simple values below 32 only have a one byte encoding
+            0x18 if bits < 0x20 => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+            0x19 => Token::Float(f16_to_f64(bits as u16)),
+            0x1a => Token::Float(f32::from_bits(bits as u32).into()),
+            0x1b => Token::Float(f64::from_bits(bits)),
+            _ => Token::Simple(bits as u8),
+        },
+        (7, None) => Token::Break,
+        _ => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+    };
+    Ok(token)
+}
+
+/// Converts the length of a string, array or map to a usize.
+pub(crate) fn to_usize(len: u64) -> Result<usize> {
+    usize::try_from(len).map_err(|_| LengthOutOfRange::new::<usize>().into())
+}
+
+/// Calls `f` with the index of every item of an array or map with `len` items, or of every
+/// item up to the break code if `len` is `None`.
+pub(crate) fn items<R: Reader + ?Sized>(
+    r: &mut R,
+    len: Option<u64>,
+    mut f: impl FnMut(&mut R, usize) -> Result<()>,
+) -> Result<()> {
+    match len {
+        Some(len) => {
+            for i in 0..to_usize(len)? {
+                f(r, i)?;
+            }
+        }
+        None => {
+            for i in 0.. {
+                if read_u8(r)? == 0xff {
+                    break;
+                }
+                r.advance(-1)?;
+                f(r, i)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with the length of every chunk of the string started by `token`.
+fn chunks<R: Reader + ?Sized>(
+    r: &mut R,
+    token: Token,
+    mut f: impl FnMut(&mut R, usize) -> Result<()>,
+) -> Result<()> {
+    match token {
+        Token::Bytes(Some(len)) | Token::Text(Some(len)) => f(r, to_usize(len)?),
+        Token::Bytes(None) | Token::Text(None) => loop {
+            let major = read_u8(r)?;
+            match (token, read_token_from(r, major)?) {
+                (_, Token::Break) => return Ok(()),
+                (Token::Bytes(_), Token::Bytes(Some(len)))
+                | (Token::Text(_), Token::Text(Some(len))) => f(r, to_usize(len)?)?,
+                _ => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+            }
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Reads the payload following a [`Token::Bytes`] or [`Token::Text`], concatenating the chunks
+/// of indefinite length strings. Other tokens have no payload.
+pub fn read_payload<R: Reader + ?Sized>(r: &mut R, token: Token) -> Result<Vec<u8>> {
+    if let Token::Bytes(Some(len)) | Token::Text(Some(len)) = token {
+        return read_bytes(r, to_usize(len)?);
+    }
+    let mut bytes = Vec::new();
+    chunks(r, token, |r, len| {
+        bytes.extend(read_bytes(r, len)?);
+        Ok(())
+    })?;
+    Ok(bytes)
+}
+
+/// Skips the payload following a [`Token::Bytes`] or [`Token::Text`].
+pub(crate) fn skip_payload<R: Reader + ?Sized>(r: &mut R, token: Token) -> Result<()> {
+    chunks(r, token, |r, len| skip_bytes(r, len as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Cursor;
+
+    fn tokens(hex: &str) -> Vec<Token> {
+        let bytes = hex::decode(hex).unwrap();
+        let mut r = Cursor::new(&bytes[..]);
+        let mut tokens = Vec::new();
+        while (r.position() as usize) < bytes.len() {
+            tokens.push(read_token(&mut r).unwrap());
+        }
+        tokens
+    }
+
+    #[test]
+    fn heads() {
+        assert_eq!(tokens("171818"), [Token::UInt(23), Token::UInt(24)]);
+        assert_eq!(tokens("3bffffffffffffffff"), [Token::NInt(u64::MAX)]);
+        assert_eq!(
+            tokens("5f40ff"),
+            [Token::Bytes(None), Token::Bytes(Some(0)), Token::Break]
+        );
+        assert_eq!(
+            tokens("9f80a1ff"),
+            [
+                Token::ArrayStart(None),
+                Token::ArrayStart(Some(0)),
+                Token::MapStart(Some(1)),
+                Token::Break,
+            ]
+        );
+        assert_eq!(
+            tokens("d82af4f7f820"),
+            [
+                Token::Tag(42),
+                Token::Simple(20),
+                Token::Simple(23),
+                Token::Simple(32),
+            ]
+        );
+        assert_eq!(
+            tokens("f93c00fa3f800000fb3ff0000000000000"),
+            [Token::Float(1.0); 3]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        for hex in ["1c", "1f", "3f", "df", "fc", "f800", "f81f"] {
+            let bytes = hex::decode(hex).unwrap();
+            read_token(&mut Cursor::new(&bytes[..]))
+                .unwrap_err()
+                .downcast::<UnexpectedCode>()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn payload() {
+        // (_ h'01', h'0203')
+        let bytes = hex::decode("5f4101420203ff").unwrap();
+        let mut r = Cursor::new(&bytes[..]);
+        let token = read_token(&mut r).unwrap();
+        assert_eq!(read_payload(&mut r, token).unwrap(), [1, 2, 3]);
+
+        // a text chunk inside a byte string
+        let bytes = hex::decode("5f6161ff").unwrap();
+        let mut r = Cursor::new(&bytes[..]);
+        let token = read_token(&mut r).unwrap();
+        let err = read_payload(&mut r, token).unwrap_err();
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0x61);
+    }
+}
@@ -0,0 +1,188 @@
+//! Rewriting of links without decoding.
+use crate::{
+    decode::{read_head, read_link, read_u8},
+    error::{UnexpectedCode, UnexpectedEof},
+    RawCborCodec,
+};
+use libipld_core::{cid::Cid, codec::Encode, error::Result, ipld::Ipld};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+/// Copies `n` bytes from `r` to `w`.
+fn copy_bytes<R: Read, W: Write>(r: &mut R, w: &mut W, n: u64) -> Result<()> {
+    if std::io::copy(&mut r.take(n), w)? != n {
+        return Err(UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// Copies the items of an array or map with `n` entries of `width` items each, or until the
+/// break code if `n` is `None`.
+fn transcode_items<R, W, F>(
+    r: &mut R,
+    w: &mut W,
+    f: &mut F,
+    n: Option<u64>,
+    width: u64,
+) -> Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(Cid) -> Result<Cid>,
+{
+    match n {
+        Some(n) => {
+            for _ in 0..n {
+                for _ in 0..width {
+                    transcode(r, w, f)?;
+                }
+            }
+        }
+        None => loop {
+            if read_u8(r)? == 0xff {
+                w.write_all(&[0xff])?;
+                break;
+            }
+            r.seek(SeekFrom::Current(-1))?;
+            for _ in 0..width {
+                transcode(r, w, f)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Copies the chunks of an indefinite length string of major type `major`, including the break
+/// code.
+///
+/// Only definite length strings of the same major type are valid chunks.
+fn transcode_chunks<R: Read + Seek, W: Write>(r: &mut R, w: &mut W, major: u8) -> Result<()> {
+    loop {
+        let mut head = [0; 9];
+        let (len, arg) = read_head(r, &mut head)?;
+        w.write_all(&head[..len])?;
+        match arg {
+            None if head[0] == 0xff => return Ok(()),
+            Some(n) if head[0] >> 5 == major => copy_bytes(r, w, n)?,
+            _ => return Err(UnexpectedCode::new::<Ipld>(head[0]).into()),
+        }
+    }
+}
+
+fn transcode<R, W, F>(r: &mut R, w: &mut W, f: &mut F) -> Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(Cid) -> Result<Cid>,
+{
+    let mut head = [0; 9];
+    let (len, arg) = read_head(r, &mut head)?;
+    let major = head[0];
+    if major >> 5 == 6 && arg == Some(42) {
+        let cid = read_link(r)?;
+        return f(cid)?.encode(RawCborCodec, w);
+    }
+    w.write_all(&head[..len])?;
+    match (major >> 5, arg) {
+        // Major type 2 and 3: a byte or text string
+        (2 | 3, Some(n)) => copy_bytes(r, w, n)?,
+        // Major type 2 and 3: a byte or text string (indefinite length)
+        (2 | 3, None) => transcode_chunks(r, w, major >> 5)?,
+        // Major type 4: an array of data items
+        (4, n) => transcode_items(r, w, f, n, 1)?,
+        // Major type 5: a map of pairs of data items
+        (5, n) => transcode_items(r, w, f, n, 2)?,
+        // Major type 6: optional semantic tagging of other major types
+        (6, _) => transcode(r, w, f)?,
+        // a break outside of an indefinite length item
+        (7, None) => return Err(UnexpectedCode::new::<Ipld>(major).into()),
+        // numbers and simple values are fully contained in the head
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Copies a single item from `r` to `w`, replacing every link with the result of `f`.
+///
+/// All other bytes are copied verbatim, so map order, non-minimal heads and tags other than 42
+/// are preserved. Rewritten links are always written with the canonical tag 42 head.
+pub fn rewrite_links<R, W, F>(r: &mut R, w: &mut W, mut f: F) -> Result<()>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(Cid) -> Result<Cid>,
+{
+    transcode(r, w, &mut f)
+}
+
+/// Rewrites all links in a cbor encoded byte slice containing a single item, see
+/// [`rewrite_links`].
+pub fn rewrite_links_in_slice<F>(bytes: &[u8], f: F) -> Result<Vec<u8>>
+where
+    F: FnMut(Cid) -> Result<Cid>,
+{
+    let mut out = Vec::with_capacity(bytes.len());
+    rewrite_links(&mut Cursor::new(bytes), &mut out, f)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::codec::Codec;
+    use multihash::{Code, MultihashDigest};
+
+    #[test]
+    fn rewrite() {
+        let old = Cid::new_v1(0x71, Code::Sha2_256.digest(&b"old"[..]));
+        let new = Cid::new_v1(0x71, Code::Blake3_256.digest(&b"old"[..]));
+        let mut expected = Vec::new();
+        // a map with unsorted keys and a non-minimal head
+        expected.extend_from_slice(&[0xb8, 0x03]);
+        expected.extend_from_slice(&[0x61, b'z', 0x19, 0x00, 0x01]);
+        expected.extend_from_slice(&[0x61, b'a']);
+        let mut input = expected.clone();
+        input.extend(RawCborCodec.encode(&old).unwrap());
+        expected.extend(RawCborCodec.encode(&new).unwrap());
+        // a non-42 tag wrapping an indefinite length array containing a link
+        for buf in [&mut input, &mut expected] {
+            buf.extend_from_slice(&[0x61, b'm', 0xd9, 0xd9, 0xf7, 0x9f]);
+        }
+        input.extend(RawCborCodec.encode(&old).unwrap());
+        expected.extend(RawCborCodec.encode(&new).unwrap());
+        input.push(0xff);
+        expected.push(0xff);
+
+        let mut seen = Vec::new();
+        let output = rewrite_links_in_slice(&input, |cid| {
+            seen.push(cid);
+            Ok(new)
+        })
+        .unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(seen, vec![old, old]);
+    }
+
+    #[test]
+    fn error() {
+        let cid = Cid::new_v1(0x71, Code::Sha2_256.digest(&b"cid"[..]));
+        let bytes = RawCborCodec.encode(&vec![cid]).unwrap();
+        assert!(rewrite_links_in_slice(&bytes, |_| Err(UnexpectedEof.into())).is_err());
+        assert!(rewrite_links_in_slice(&bytes[..bytes.len() - 1], Ok).is_err());
+        assert!(rewrite_links_in_slice(&[0xff], Ok).is_err());
+        // a map claiming more pairs than fit in a u64 item count
+        let huge = [0xbb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert!(rewrite_links_in_slice(&huge, Ok).is_err());
+        // indefinite length byte strings with a link, a text and an indefinite length chunk
+        let mut link = vec![0x5f];
+        link.extend(RawCborCodec.encode(&cid).unwrap());
+        link.push(0xff);
+        for bytes in [
+            link,
+            vec![0x5f, 0x61, b'a', 0xff],
+            vec![0x5f, 0x5f, 0xff, 0xff],
+        ] {
+            let err = rewrite_links_in_slice(&bytes, |_| panic!("not a chunk")).unwrap_err();
+            err.downcast::<UnexpectedCode>().unwrap();
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Typed arrays (RFC 8746) for numeric vectors.
+//!
+//! A [`TypedArray`] encodes its elements as a single byte string behind a typed array tag instead
+//! of an array with one head per element.
+use crate::{
+    decode::{read_list, read_list_il, read_u8},
+    encode::{write_tag, write_u64},
+    encoded_len::{head_len, EncodedLen},
+    error::{LengthOutOfRange, UnexpectedCode, UnknownTag},
+    io::{Read, Seek, Write, Writer},
+    token::{read_payload, read_token_from, to_usize, Token},
+    RawCborCodec,
+};
+use alloc::vec::Vec;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use libipld_core::{
+    codec::{Decode, Encode},
+    error::Result,
+};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Numeric types that can be the elements of a [`TypedArray`].
+///
+/// The typed array tags are a closed set, so this trait is sealed.
+pub trait Element: Copy + Decode<RawCborCodec> + private::Sealed {
+    /// Tag of a typed array with big endian elements.
+    const BIG_ENDIAN_TAG: u64;
+    /// Tag of a typed array with little endian elements.
+    const LITTLE_ENDIAN_TAG: u64;
+    /// Number of bytes of an element.
+    const SIZE: usize;
+
+    /// Reads an element from `SIZE` big endian bytes.
+    fn read_be(bytes: &[u8]) -> Self;
+
+    /// Reads an element from `SIZE` little endian bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+
+    /// Writes the element into `SIZE` big endian bytes.
+    fn write_be(self, bytes: &mut [u8]);
+}
+
+impl private::Sealed for u8 {}
+
+/// Single bytes have no endianness, little endian `u8` arrays are the clamped variant.
+impl Element for u8 {
+    const BIG_ENDIAN_TAG: u64 = 64;
+    const LITTLE_ENDIAN_TAG: u64 = 68;
+    const SIZE: usize = 1;
+
+    fn read_be(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        bytes[0]
+    }
+
+    fn write_be(self, bytes: &mut [u8]) {
+        bytes[0] = self;
+    }
+}
+
+impl private::Sealed for i8 {}
+
+/// Single bytes have no endianness, so both tags are the same.
+impl Element for i8 {
+    const BIG_ENDIAN_TAG: u64 = 72;
+    const LITTLE_ENDIAN_TAG: u64 = 72;
+    const SIZE: usize = 1;
+
+    fn read_be(bytes: &[u8]) -> Self {
+        bytes[0] as i8
+    }
+
+    fn read_le(bytes: &[u8]) -> Self {
+        bytes[0] as i8
+    }
+
+    fn write_be(self, bytes: &mut [u8]) {
+        bytes[0] = self as u8;
+    }
+}
+
+macro_rules! element {
+    ($ty:ty, $tag:expr, $read:ident, $write:ident) => {
+        impl private::Sealed for $ty {}
+
+        impl Element for $ty {
+            const BIG_ENDIAN_TAG: u64 = $tag;
+            const LITTLE_ENDIAN_TAG: u64 = $tag + 4;
+            const SIZE: usize = core::mem::size_of::<$ty>();
+
+            fn read_be(bytes: &[u8]) -> Self {
+                BigEndian::$read(bytes)
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                LittleEndian::$read(bytes)
+            }
+
+            fn write_be(self, bytes: &mut [u8]) {
+                BigEndian::$write(bytes, self)
+            }
+        }
+    };
+}
+
+element!(u16, 65, read_u16, write_u16);
+element!(u32, 66, read_u32, write_u32);
+element!(u64, 67, read_u64, write_u64);
+element!(i16, 73, read_i16, write_i16);
+element!(i32, 74, read_i32, write_i32);
+element!(i64, 75, read_i64, write_i64);
+element!(f32, 81, read_f32, write_f32);
+element!(f64, 82, read_f64, write_f64);
+
+/// A vector of numbers encoded as an RFC 8746 typed array.
+///
+/// Encoding always writes big endian elements. Decoding accepts either endianness, and also plain
+/// arrays so that existing data keeps decoding after switching a field from `Vec<T>` to
+/// `TypedArray<T>`.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct TypedArray<T>(pub Vec<T>);
+
+impl<T> From<Vec<T>> for TypedArray<T> {
+    fn from(elements: Vec<T>) -> Self {
+        Self(elements)
+    }
+}
+
+impl<T> From<TypedArray<T>> for Vec<T> {
+    fn from(array: TypedArray<T>) -> Self {
+        array.0
+    }
+}
+
+impl<T: Element> Encode<RawCborCodec> for TypedArray<T> {
+    fn encode<W: Write>(&self, _: RawCborCodec, w: &mut W) -> Result<()> {
+        write_tag(w, T::BIG_ENDIAN_TAG)?;
+        write_u64(w, 2, (self.0.len() * T::SIZE) as u64)?;
+        let mut buf = [0; 8];
+        for element in &self.0 {
+            element.write_be(&mut buf[..T::SIZE]);
+            Writer::write_all(w, &buf[..T::SIZE])?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Element> Decode<RawCborCodec> for TypedArray<T> {
+    fn decode<R: Read + Seek>(_: RawCborCodec, r: &mut R) -> Result<Self> {
+        let major = read_u8(r)?;
+        match read_token_from(r, major)? {
+            Token::Tag(tag) if tag == T::BIG_ENDIAN_TAG || tag == T::LITTLE_ENDIAN_TAG => {
+                let major = read_u8(r)?;
+                let token = read_token_from(r, major)?;
+                if !matches!(token, Token::Bytes(_)) {
+                    return Err(UnexpectedCode::new::<Self>(major).into());
+                }
+                let bytes = read_payload(r, token)?;
+                if bytes.len() % T::SIZE != 0 {
+                    return Err(LengthOutOfRange::new::<Self>().into());
+                }
+                let read = if tag == T::BIG_ENDIAN_TAG {
+                    T::read_be
+                } else {
+                    T::read_le
+                };
+                Ok(Self(bytes.chunks_exact(T::SIZE).map(read).collect()))
+            }
+            Token::Tag(tag) => Err(UnknownTag(tag).into()),
+            Token::ArrayStart(Some(len)) => Ok(Self(read_list(r, to_usize(len)?)?)),
+            Token::ArrayStart(None) => Ok(Self(read_list_il(r)?)),
+            _ => Err(UnexpectedCode::new::<Self>(major).into()),
+        }
+    }
+}
+
+impl<T: Element> EncodedLen for TypedArray<T> {
+    fn encoded_len(&self) -> usize {
+        let len = self.0.len() * T::SIZE;
+        head_len(T::BIG_ENDIAN_TAG) + head_len(len as u64) + len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::codec::Codec;
+
+    #[test]
+    fn roundtrip() {
+        let array = TypedArray(vec![1.5f32, -2.0, f32::INFINITY]);
+        let bytes = RawCborCodec.encode(&array).unwrap();
+        assert_eq!(hex::encode(&bytes), "d8514c3fc00000c00000007f800000");
+        assert_eq!(bytes.len(), array.encoded_len());
+        assert_eq!(
+            RawCborCodec.decode::<TypedArray<f32>>(&bytes).unwrap(),
+            array
+        );
+
+        let array = TypedArray(vec![i64::MIN, -1, 0, i64::MAX]);
+        let bytes = RawCborCodec.encode(&array).unwrap();
+        assert_eq!(bytes.len(), array.encoded_len());
+        assert_eq!(
+            RawCborCodec.decode::<TypedArray<i64>>(&bytes).unwrap(),
+            array
+        );
+
+        let array = TypedArray(vec![0u8, 255]);
+        let bytes = RawCborCodec.encode(&array).unwrap();
+        assert_eq!(hex::encode(&bytes), "d8404200ff");
+    }
+
+    #[test]
+    fn little_endian() {
+        // uint16, little endian: [1, 256]
+        let bytes = hex::decode("d8454401000001").unwrap();
+        let array = RawCborCodec.decode::<TypedArray<u16>>(&bytes).unwrap();
+        assert_eq!(array.0, [1, 256]);
+
+        // clamped uint8
+        let bytes = hex::decode("d8444107").unwrap();
+        let array = RawCborCodec.decode::<TypedArray<u8>>(&bytes).unwrap();
+        assert_eq!(array.0, [7]);
+    }
+
+    #[test]
+    fn plain_arrays() {
+        let bytes = RawCborCodec.encode(&vec![1u16, 1000]).unwrap();
+        let array = RawCborCodec.decode::<TypedArray<u16>>(&bytes).unwrap();
+        assert_eq!(array.0, [1, 1000]);
+
+        // [_ 1.5]
+        let bytes = hex::decode("9ffa3fc00000ff").unwrap();
+        let array = RawCborCodec.decode::<TypedArray<f64>>(&bytes).unwrap();
+        assert_eq!(array.0, [1.5]);
+    }
+
+    #[test]
+    fn invalid() {
+        // uint16 typed array with an odd number of bytes
+        let err = RawCborCodec
+            .decode::<TypedArray<u16>>(&hex::decode("d84143010203").unwrap())
+            .unwrap_err();
+        err.downcast::<LengthOutOfRange>().unwrap();
+
+        // a float32 typed array is not an uint32 typed array
+        let err = RawCborCodec
+            .decode::<TypedArray<u32>>(&hex::decode("d8514400000000").unwrap())
+            .unwrap_err();
+        assert_eq!(err.downcast::<UnknownTag>().unwrap().0, 81);
+    }
+}
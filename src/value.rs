@@ -0,0 +1,277 @@
+//! A lossless representation of any well-formed cbor item.
+//!
+//! Unlike `Ipld`, a [`Value`] keeps tags, simple values including undefined, the width of
+//! floats, indefinite lengths and map entries in their original order, so decoding and encoding
+//! it again writes the same bytes. Only heads are normalized: integers, lengths and tag numbers
+//! are written with the shortest head.
+use crate::{
+    decode::{
+        map_key, map_value, read_bytes, read_list, read_list_il, read_u16, read_u32, read_u64,
+        read_u8,
+    },
+    encode::{write_tag, write_u64},
+    encoded_len::{head_len, EncodedLen},
+    error::UnexpectedCode,
+    io::{Read, Reader, Seek, Write, Writer},
+    simple::Simple,
+    token::{items, read_payload, read_token_from, to_usize, Token},
+    RawCborCodec,
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use libipld_core::{
+    codec::{Decode, Encode},
+    error::Result,
+};
+
+/// Any well-formed cbor item.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An unsigned or negative integer, between `-2^64` and `2^64 - 1`.
+    Integer(i128),
+    /// A definite length byte string.
+    Bytes(Vec<u8>),
+    /// An indefinite length byte string, as its chunks.
+    IndefiniteBytes(Vec<Vec<u8>>),
+    /// A definite length text string.
+    Text(String),
+    /// An indefinite length text string, as its chunks.
+    IndefiniteText(Vec<String>),
+    /// A definite length array.
+    Array(Vec<Value>),
+    /// An indefinite length array.
+    IndefiniteArray(Vec<Value>),
+    /// A definite length map, as its entries in order, including duplicate keys.
+    Map(Vec<(Value, Value)>),
+    /// An indefinite length map, as its entries in order.
+    IndefiniteMap(Vec<(Value, Value)>),
+    /// A tagged item, e.g. a link with tag 42.
+    Tag(u64, Box<Value>),
+    /// A simple value, including false, true, null and undefined.
+    Simple(Simple),
+    /// A half precision float, as its bits.
+    Float16(u16),
+    /// A single precision float.
+    Float32(f32),
+    /// A double precision float.
+    Float64(f64),
+}
+
+/// Writes the head of an indefinite length item of major type `major`.
+fn write_indefinite<W: Writer + ?Sized>(w: &mut W, major: u8) -> Result<()> {
+    w.write_all(&[major << 5 | 0x1f])
+}
+
+fn write_break<W: Writer + ?Sized>(w: &mut W) -> Result<()> {
+    w.write_all(&[0xff])
+}
+
+fn write_entries<W: Write>(entries: &[(Value, Value)], c: RawCborCodec, w: &mut W) -> Result<()> {
+    for (key, value) in entries {
+        key.encode(c, w)?;
+        value.encode(c, w)?;
+    }
+    Ok(())
+}
+
+impl Encode<RawCborCodec> for Value {
+    fn encode<W: Write>(&self, c: RawCborCodec, w: &mut W) -> Result<()> {
+        match self {
+            Self::Integer(n) => n.encode(c, w),
+            Self::Bytes(bytes) => bytes.as_slice().encode(c, w),
+            Self::IndefiniteBytes(chunks) => {
+                write_indefinite(w, 2)?;
+                for chunk in chunks {
+                    chunk.as_slice().encode(c, w)?;
+                }
+                write_break(w)
+            }
+            Self::Text(text) => text.encode(c, w),
+            Self::IndefiniteText(chunks) => {
+                write_indefinite(w, 3)?;
+                for chunk in chunks {
+                    chunk.encode(c, w)?;
+                }
+                write_break(w)
+            }
+            Self::Array(values) => values.encode(c, w),
+            Self::IndefiniteArray(values) => {
+                write_indefinite(w, 4)?;
+                for value in values {
+                    value.encode(c, w)?;
+                }
+                write_break(w)
+            }
+            Self::Map(entries) => {
+                write_u64(w, 5, entries.len() as u64)?;
+                write_entries(entries, c, w)
+            }
+            Self::IndefiniteMap(entries) => {
+                write_indefinite(w, 5)?;
+                write_entries(entries, c, w)?;
+                write_break(w)
+            }
+            Self::Tag(tag, value) => {
+                write_tag(w, *tag)?;
+                value.encode(c, w)
+            }
+            Self::Simple(simple) => simple.encode(c, w),
+            Self::Float16(bits) => {
+                let [a, b] = bits.to_be_bytes();
+                Writer::write_all(w, &[0xf9, a, b])
+            }
+            Self::Float32(x) => {
+                let [a, b, c, d] = x.to_bits().to_be_bytes();
+                Writer::write_all(w, &[0xfa, a, b, c, d])
+            }
+            Self::Float64(x) => {
+                Writer::write_all(w, &[0xfb])?;
+                Writer::write_all(w, &x.to_bits().to_be_bytes())
+            }
+        }
+    }
+}
+
+/// Reads the chunks of an indefinite length string of major type `major` with `f`.
+fn read_chunks<R: Reader + ?Sized, T>(
+    r: &mut R,
+    major: u8,
+    mut f: impl FnMut(Vec<u8>) -> Result<T>,
+) -> Result<Vec<T>> {
+    let mut chunks = Vec::new();
+    items(r, None, |r, _| {
+        let code = read_u8(r)?;
+        match read_token_from(r, code)? {
+            Token::Bytes(Some(len)) | Token::Text(Some(len)) if code >> 5 == major => {
+                chunks.push(f(read_bytes(r, to_usize(len)?)?)?);
+                Ok(())
+            }
+            _ => Err(UnexpectedCode::new::<Value>(code).into()),
+        }
+    })?;
+    Ok(chunks)
+}
+
+fn read_entries<R: Read + Seek>(r: &mut R, len: Option<u64>) -> Result<Vec<(Value, Value)>> {
+    let mut entries = Vec::new();
+    items(r, len, |r, _| {
+        let (key, offset) = map_key(r, |r| Value::decode(RawCborCodec, r))?;
+        let value = map_value(r, offset, |r| Value::decode(RawCborCodec, r))?;
+        entries.push((key, value));
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+impl Decode<RawCborCodec> for Value {
+    fn decode<R: Read + Seek>(c: RawCborCodec, r: &mut R) -> Result<Self> {
+        let major = read_u8(r)?;
+        // the token of a float no longer tells its width
+        match major {
+            0xf9 => return Ok(Self::Float16(read_u16(r)?)),
+            0xfa => return Ok(Self::Float32(f32::from_bits(read_u32(r)?))),
+            0xfb => return Ok(Self::Float64(f64::from_bits(read_u64(r)?))),
+            _ => {}
+        }
+        let value = match read_token_from(r, major)? {
+            Token::UInt(n) => Self::Integer(n as i128),
+            Token::NInt(n) => Self::Integer(-1 - n as i128),
+            token @ Token::Bytes(Some(_)) => Self::Bytes(read_payload(r, token)?),
+            Token::Bytes(None) => Self::IndefiniteBytes(read_chunks(r, 2, Ok)?),
+            token @ Token::Text(Some(_)) => Self::Text(String::from_utf8(read_payload(r, token)?)?),
+            Token::Text(None) => {
+                Self::IndefiniteText(read_chunks(r, 3, |bytes| Ok(String::from_utf8(bytes)?))?)
+            }
+            Token::ArrayStart(Some(len)) => Self::Array(read_list(r, to_usize(len)?)?),
+            Token::ArrayStart(None) => Self::IndefiniteArray(read_list_il(r)?),
+            Token::MapStart(len @ Some(_)) => Self::Map(read_entries(r, len)?),
+            Token::MapStart(None) => Self::IndefiniteMap(read_entries(r, None)?),
+            Token::Tag(tag) => Self::Tag(tag, Box::new(Self::decode(c, r)?)),
+            Token::Simple(value) => match Simple::new(value) {
+                Some(simple) => Self::Simple(simple),
+                None => return Err(UnexpectedCode::new::<Self>(major).into()),
+            },
+            Token::Float(_) | Token::Break => return Err(UnexpectedCode::new::<Self>(major).into()),
+        };
+        Ok(value)
+    }
+}
+
+impl EncodedLen for Value {
+    fn encoded_len(&self) -> usize {
+        fn entries_len(entries: &[(Value, Value)]) -> usize {
+            entries
+                .iter()
+                .map(|(key, value)| key.encoded_len() + value.encoded_len())
+                .sum()
+        }
+
+        match self {
+            Self::Integer(n) => n.encoded_len(),
+            Self::Bytes(bytes) => bytes.as_slice().encoded_len(),
+            Self::IndefiniteBytes(chunks) => {
+                2 + chunks
+                    .iter()
+                    .map(|c| c.as_slice().encoded_len())
+                    .sum::<usize>()
+            }
+            Self::Text(text) => text.encoded_len(),
+            Self::IndefiniteText(chunks) => {
+                2 + chunks.iter().map(String::encoded_len).sum::<usize>()
+            }
+            Self::Array(values) => values.encoded_len(),
+            Self::IndefiniteArray(values) => {
+                2 + values.iter().map(Value::encoded_len).sum::<usize>()
+            }
+            Self::Map(entries) => head_len(entries.len() as u64) + entries_len(entries),
+            Self::IndefiniteMap(entries) => 2 + entries_len(entries),
+            Self::Tag(tag, value) => head_len(*tag) + value.encoded_len(),
+            Self::Simple(simple) => simple.encoded_len(),
+            Self::Float16(_) => 3,
+            Self::Float32(_) => 5,
+            Self::Float64(_) => 9,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Location;
+    use libipld_core::codec::Codec;
+
+    #[test]
+    fn roundtrip() {
+        for hex in [
+            // 1(-1.5) as a half float, undefined and simple(255)
+            "83c1f9be00f7f8ff",
+            // {1: "a", 1: "b"} with a duplicate integer key
+            "a2016161016162",
+            // an indefinite length string with a single chunk and NaN with a payload
+            "827f6161fffa7fc00001",
+        ] {
+            let bytes = hex::decode(hex).unwrap();
+            let value: Value = RawCborCodec.decode(&bytes).unwrap();
+            assert_eq!(value.encoded_len(), bytes.len());
+            assert_eq!(RawCborCodec.encode(&value).unwrap(), bytes);
+        }
+        let value: Value = RawCborCodec.decode(&[0xc1, 0xf7]).unwrap();
+        assert_eq!(
+            value,
+            Value::Tag(1, Box::new(Value::Simple(Simple::UNDEFINED)))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        // a text chunk in a byte string and a break outside of an indefinite length item
+        assert!(RawCborCodec
+            .decode::<Value>(&[0x5f, 0x61, 0x61, 0xff])
+            .is_err());
+        let err = RawCborCodec
+            .decode::<Value>(&[0xa1, 0x61, 0x61, 0xff])
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<Location>().unwrap().offset, 3);
+        assert_eq!(err.downcast::<UnexpectedCode>().unwrap().code, 0xff);
+        assert!(RawCborCodec.encode(&Value::Integer(1 << 64)).is_err());
+    }
+}
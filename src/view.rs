@@ -0,0 +1,350 @@
+//! Lazy navigable view over cbor encoded bytes.
+use crate::{
+    decode::read_u8,
+    error::UnexpectedCode,
+    path::{next_item, seek_index, seek_key, seek_path, PathSegment},
+    token::{read_payload, read_token, read_token_from, to_usize, Token},
+    RawCborCodec,
+};
+use libipld_core::{
+    codec::{Codec, Decode, Encode},
+    error::Result,
+    raw_value::SkipOne,
+};
+use std::io::Cursor;
+
+/// The kind of a cbor item, as given by its major type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    /// Major type 0: an unsigned integer.
+    UnsignedInteger,
+    /// Major type 1: a negative integer.
+    NegativeInteger,
+    /// Major type 2: a byte string.
+    Bytes,
+    /// Major type 3: a text string.
+    String,
+    /// Major type 4: an array of data items.
+    Array,
+    /// Major type 5: a map of pairs of data items.
+    Map,
+    /// Major type 6: a tagged data item.
+    Tag,
+    /// Major type 7: a floating-point number.
+    Float,
+    /// Major type 7: a simple value such as a bool or null.
+    Simple,
+}
+
+/// A reference to a single cbor item inside a byte slice.
+///
+/// Navigating does not decode anything but the heads needed to find the requested item, so
+/// unrelated items are skipped without being materialised.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CborRef<'a> {
+    bytes: &'a [u8],
+}
+
+/// Splits off the first item of `r`.
+fn next_ref<'a>(r: &mut Cursor<&'a [u8]>) -> Result<CborRef<'a>> {
+    let start = r.position() as usize;
+    RawCborCodec.skip(r)?;
+    let end = r.position() as usize;
+    Ok(CborRef {
+        bytes: &r.get_ref()[start..end],
+    })
+}
+
+impl<'a> CborRef<'a> {
+    /// Creates a reference to the first item in `bytes`. Trailing bytes are ignored.
+    pub fn new(bytes: &'a [u8]) -> Result<Self> {
+        next_ref(&mut Cursor::new(bytes))
+    }
+
+    /// The encoded bytes of this item.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The kind of this item.
+    pub fn kind(&self) -> Kind {
+        let major = self.bytes[0];
+        match major >> 5 {
+            0 => Kind::UnsignedInteger,
+            1 => Kind::NegativeInteger,
+            2 => Kind::Bytes,
+            3 => Kind::String,
+            4 => Kind::Array,
+            5 => Kind::Map,
+            6 => Kind::Tag,
+            _ => match major {
+                0xf9..=0xfb => Kind::Float,
+                _ => Kind::Simple,
+            },
+        }
+    }
+
+    /// The number of bytes of a byte or text string, the number of elements of an array or the
+    /// number of entries of a map, or `None` for all other kinds.
+    ///
+    /// For indefinite length arrays and maps this needs to walk over all items.
+    pub fn len(&self) -> Result<Option<usize>> {
+        let len = match self.kind() {
+            Kind::Bytes | Kind::String => {
+                let mut r = self.cursor();
+                match read_token(&mut r)? {
+                    Token::Bytes(Some(len)) | Token::Text(Some(len)) => Some(to_usize(len)?),
+                    // the chunks of an indefinite length string
+                    token => Some(read_payload(&mut r, token)?.len()),
+                }
+            }
+            Kind::Array => {
+                let mut n = 0;
+                for item in self.elements()? {
+                    item?;
+                    n += 1;
+                }
+                Some(n)
+            }
+            Kind::Map => {
+                let mut n = 0;
+                for entry in self.entries()? {
+                    entry?;
+                    n += 1;
+                }
+                Some(n)
+            }
+            _ => None,
+        };
+        Ok(len)
+    }
+
+    /// Returns `true` if this is a string, array or map without any bytes, elements or entries.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == Some(0))
+    }
+
+    /// The tag number and the tagged item if this is a tag.
+    pub fn tag(&self) -> Result<Option<(u64, CborRef<'a>)>> {
+        let mut r = self.cursor();
+        match read_token(&mut r)? {
+            Token::Tag(tag) => Ok(Some((tag, next_ref(&mut r)?))),
+            _ => Ok(None),
+        }
+    }
+
+    /// The value for `key` if this is a map containing it.
+    ///
+    /// Keys are compared in their encoded form, see [`PathSegment::Key`].
+    pub fn get<K: Encode<RawCborCodec> + ?Sized>(&self, key: &K) -> Result<Option<CborRef<'a>>> {
+        let key = RawCborCodec.encode(key)?;
+        let mut r = self.cursor();
+        if !seek_key(&mut r, &key)? {
+            return Ok(None);
+        }
+        Ok(Some(next_ref(&mut r)?))
+    }
+
+    /// The element at `index` if this is an array that is long enough.
+    pub fn index(&self, index: usize) -> Result<Option<CborRef<'a>>> {
+        let mut r = self.cursor();
+        if !seek_index(&mut r, index)? {
+            return Ok(None);
+        }
+        Ok(Some(next_ref(&mut r)?))
+    }
+
+    /// The item addressed by `path`, if it exists.
+    pub fn path(&self, path: &[PathSegment]) -> Result<Option<CborRef<'a>>> {
+        let mut r = self.cursor();
+        if !seek_path(&mut r, path)? {
+            return Ok(None);
+        }
+        Ok(Some(next_ref(&mut r)?))
+    }
+
+    /// An iterator over the elements of an array.
+    pub fn elements(&self) -> Result<Elements<'a>> {
+        let mut r = self.cursor();
+        let major = read_u8(&mut r)?;
+        let len = match read_token_from(&mut r, major)? {
+            Token::ArrayStart(len) => len.map(to_usize).transpose()?,
+            _ => return Err(UnexpectedCode::new::<Elements>(major).into()),
+        };
+        Ok(Elements {
+            r,
+            len,
+            done: false,
+        })
+    }
+
+    /// An iterator over the entries of a map.
+    pub fn entries(&self) -> Result<Entries<'a>> {
+        let mut r = self.cursor();
+        let major = read_u8(&mut r)?;
+        let len = match read_token_from(&mut r, major)? {
+            Token::MapStart(len) => len.map(to_usize).transpose()?,
+            _ => return Err(UnexpectedCode::new::<Entries>(major).into()),
+        };
+        Ok(Entries {
+            r,
+            len,
+            done: false,
+        })
+    }
+
+    /// Decodes this item.
+    pub fn decode<T: Decode<RawCborCodec>>(&self) -> Result<T> {
+        RawCborCodec.decode(self.bytes)
+    }
+
+    fn cursor(&self) -> Cursor<&'a [u8]> {
+        Cursor::new(self.bytes)
+    }
+}
+
+/// Advances an array or map iterator, fusing it after the last item or the first error.
+fn advance<'a, T>(
+    r: &mut Cursor<&'a [u8]>,
+    len: &mut Option<usize>,
+    done: &mut bool,
+    f: impl FnOnce(&mut Cursor<&'a [u8]>) -> Result<T>,
+) -> Option<Result<T>> {
+    if *done {
+        return None;
+    }
+    let item = match next_item(r, len) {
+        Ok(true) => f(r),
+        Ok(false) => {
+            *done = true;
+            return None;
+        }
+        Err(err) => Err(err),
+    };
+    if item.is_err() {
+        *done = true;
+    }
+    Some(item)
+}
+
+/// Iterator over the elements of an array, see [`CborRef::elements`].
+#[derive(Debug)]
+pub struct Elements<'a> {
+    r: Cursor<&'a [u8]>,
+    len: Option<usize>,
+    done: bool,
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Result<CborRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        advance(&mut self.r, &mut self.len, &mut self.done, next_ref)
+    }
+}
+
+/// Iterator over the entries of a map, see [`CborRef::entries`].
+#[derive(Debug)]
+pub struct Entries<'a> {
+    r: Cursor<&'a [u8]>,
+    len: Option<usize>,
+    done: bool,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<(CborRef<'a>, CborRef<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        advance(&mut self.r, &mut self.len, &mut self.done, |r| {
+            Ok((next_ref(r)?, next_ref(r)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld_core::ipld::Ipld;
+    use libipld_macro::ipld;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn navigate() {
+        let ipld = ipld!({
+            "list": [1, -2, "three"],
+            "map": { "a": 1.5, "b": null },
+            "bytes": vec![0u8, 1, 2],
+        });
+        let bytes = RawCborCodec.encode(&ipld).unwrap();
+        let root = CborRef::new(&bytes).unwrap();
+        assert_eq!(root.kind(), Kind::Map);
+        assert_eq!(root.len().unwrap(), Some(3));
+
+        let list = root.get("list").unwrap().unwrap();
+        assert_eq!(list.kind(), Kind::Array);
+        assert_eq!(list.len().unwrap(), Some(3));
+        assert_eq!(
+            list.index(1).unwrap().unwrap().kind(),
+            Kind::NegativeInteger
+        );
+        assert_eq!(
+            list.index(2).unwrap().unwrap().decode::<String>().unwrap(),
+            "three"
+        );
+        assert_eq!(list.index(3).unwrap(), None);
+        let items = list
+            .elements()
+            .unwrap()
+            .map(|item| item.unwrap().decode::<Ipld>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(Ipld::List(items), ipld!([1, -2, "three"]));
+
+        let map = root.get("map").unwrap().unwrap();
+        let keys = map
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().0.decode::<String>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(map.get("a").unwrap().unwrap().kind(), Kind::Float);
+        assert_eq!(map.get("b").unwrap().unwrap().kind(), Kind::Simple);
+        assert_eq!(map.get("c").unwrap(), None);
+
+        let b = root.get("bytes").unwrap().unwrap();
+        assert_eq!(b.kind(), Kind::Bytes);
+        assert_eq!(b.len().unwrap(), Some(3));
+        assert_eq!(root.get("missing").unwrap(), None);
+        assert_eq!(root.decode::<Ipld>().unwrap(), ipld);
+    }
+
+    #[test]
+    fn non_string_keys_and_tags() {
+        let cid = RawCborCodec
+            .decode::<Ipld>(
+                &hex::decode(
+                    "d82a58250001711220f3bffba2b0bbc80a1c4ba39c789bb8e1eef08dc2792e4beb0fbaff1369b7a035",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let mut map = BTreeMap::new();
+        map.insert(7u64, cid.clone());
+        let bytes = RawCborCodec.encode(&map).unwrap();
+        let root = CborRef::new(&bytes).unwrap();
+        let link = root.get(&7u64).unwrap().unwrap();
+        assert_eq!(link.kind(), Kind::Tag);
+        let (tag, inner) = link.tag().unwrap().unwrap();
+        assert_eq!(tag, 42);
+        assert_eq!(inner.kind(), Kind::Bytes);
+        assert_eq!(link.decode::<Ipld>().unwrap(), cid);
+    }
+
+    #[test]
+    fn indefinite_length() {
+        let bytes = [0x9f, 0x01, 0x9f, 0xff, 0xff, 0xff];
+        let root = CborRef::new(&bytes).unwrap();
+        assert_eq!(root.as_bytes().len(), 5);
+        assert_eq!(root.len().unwrap(), Some(2));
+        assert!(root.index(1).unwrap().unwrap().is_empty().unwrap());
+    }
+}
@@ -0,0 +1,119 @@
+use quickcheck::{quickcheck, Arbitrary, Gen};
+
+#[path = "consistency/check.rs"]
+mod check;
+
+/// A sequence of random heads and payloads, which nest because arrays, maps and tags consume the
+/// items that follow them.
+#[derive(Clone, Debug)]
+struct Items(Vec<u8>);
+
+fn head(bytes: &mut Vec<u8>, major: u8, value: u64, size: u8) {
+    let major = major << 5;
+    match size {
+        0 if value < 24 => bytes.push(major | value as u8),
+        0 | 1 => bytes.extend_from_slice(&[major | 24, value as u8]),
+        2 => {
+            bytes.push(major | 25);
+            bytes.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        3 => {
+            bytes.push(major | 26);
+            bytes.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            bytes.push(major | 27);
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+impl Arbitrary for Items {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let mut bytes = Vec::new();
+        let n = usize::arbitrary(g) % g.size() + 1;
+        for _ in 0..n {
+            let size = u8::arbitrary(g) % 5;
+            match u8::arbitrary(g) % 12 {
+                0 => head(&mut bytes, 0, u64::arbitrary(g), size),
+                1 => head(&mut bytes, 1, u64::arbitrary(g), size),
+                kind @ (2 | 3) => {
+                    let payload = Vec::<u8>::arbitrary(g);
+                    head(&mut bytes, kind, payload.len() as u64, size);
+                    if kind == 3 && bool::arbitrary(g) {
+                        bytes.extend(payload.iter().map(|b| b'a' + b % 26));
+                    } else {
+                        bytes.extend(payload);
+                    }
+                }
+                kind @ (4 | 5) => {
+                    if bool::arbitrary(g) {
+                        head(&mut bytes, kind, u64::from(u8::arbitrary(g) % 4), size);
+                    } else {
+                        bytes.push(kind << 5 | 31);
+                    }
+                }
+                6 => {
+                    let tag = *g.choose(&[0, 1, 24, 42, 258, 55799]).unwrap();
+                    head(&mut bytes, 6, tag, size);
+                }
+                7 => {
+                    // a valid link
+                    bytes
+                        .extend_from_slice(&[0xd8, 0x2a, 0x58, 0x25, 0x00, 0x01, 0x71, 0x12, 0x20]);
+                    bytes.extend((0..32).map(|_| u8::arbitrary(g)));
+                }
+                8 => bytes.push(*g.choose(&[0xf4, 0xf5, 0xf6, 0xf7]).unwrap()),
+                9 => {
+                    let code = *g.choose(&[0xf8, 0xf9, 0xfa, 0xfb]).unwrap();
+                    bytes.push(code);
+                    let len = 1 << (code - 0xf8);
+                    bytes.extend((0..len).map(|_| u8::arbitrary(g)));
+                }
+                10 => bytes.push(0xff),
+                _ => bytes.push(u8::arbitrary(g)),
+            }
+        }
+        Self(bytes)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.0.shrink().map(Self))
+    }
+}
+
+quickcheck! {
+    fn random_bytes(bytes: Vec<u8>) -> bool {
+        check::check(&bytes);
+        true
+    }
+
+    fn random_items(items: Items) -> bool {
+        check::check(&items.0);
+        true
+    }
+}
+
+#[test]
+fn regressions() {
+    for hex in [
+        // tags with one byte heads other than 42
+        "c100",
+        // a byte string longer than the input
+        "5b00ffffffffffffff01",
+        // a byte string length that overflows i64
+        "5bffffffffffffffff",
+        // half float
+        "f93c00",
+        // simple value with a one byte payload
+        "f820",
+        // simple value without a payload
+        "e0",
+        // indefinite length byte string
+        "5f4101420203ff",
+        // text chunk in an indefinite length byte string
+        "5f6161ff",
+    ] {
+        check::check(&hex::decode(hex).unwrap());
+    }
+}
@@ -0,0 +1,75 @@
+//! Checks that `Decode for Ipld`, `SkipOne` and `References` agree on arbitrary input.
+//!
+//! Shared by the `consistency` test and the `consistency` fuzz target.
+use libipld_core::{
+    codec::{Decode, References},
+    error::Result,
+    ipld::Ipld,
+    raw_value::SkipOne,
+};
+use libipld_raw_cbor::{error::CborError, RawCborCodec};
+use std::io::Cursor;
+
+/// Runs `f` on `bytes`, returning the number of bytes consumed or the error.
+fn run<T>(bytes: &[u8], f: impl FnOnce(&mut Cursor<&[u8]>) -> Result<T>) -> Result<u64, CborError> {
+    let mut r = Cursor::new(bytes);
+    match f(&mut r) {
+        Ok(_) => {
+            assert!(r.position() <= bytes.len() as u64, "consumed past the end");
+            Ok(r.position())
+        }
+        Err(err) => Err(CborError::from(&err)),
+    }
+}
+
+/// Errors of links that are well-formed cbor but don't hold a valid cid.
+fn is_link_error(err: &CborError) -> bool {
+    matches!(
+        err,
+        CborError::InvalidLink(_)
+            | CborError::InvalidCidPrefix(_)
+            | CborError::InvalidCid
+            | CborError::LengthOutOfRange { .. }
+    )
+}
+
+/// Errors for well-formed cbor that can not be represented as `Ipld`.
+fn is_data_model_error(err: &CborError) -> bool {
+    is_link_error(err)
+        || match err {
+            CborError::UnknownTag(_) | CborError::InvalidUtf8 => true,
+            // map keys that are not strings
+            CborError::UnexpectedCode { ty, .. } if *ty == std::any::type_name::<String>() => true,
+            // simple values other than bools, null and undefined
+            CborError::UnexpectedCode {
+                code: 0xe0..=0xf3 | 0xf8,
+                ..
+            } => true,
+            _ => false,
+        }
+}
+
+/// Panics if the three decoders disagree on `bytes`.
+///
+/// Skipping only checks that the input is well-formed, collecting references additionally
+/// validates links and decoding additionally requires the data to fit the ipld data model. All
+/// three must consume the same number of bytes when they succeed.
+pub fn check(bytes: &[u8]) {
+    let skip = run(bytes, |r| RawCborCodec.skip(r));
+    let references = run(bytes, |r| {
+        <Ipld as References<RawCborCodec>>::references(RawCborCodec, r, &mut Vec::new())
+    });
+    let decode = run(bytes, |r| Ipld::decode(RawCborCodec, r));
+    match (&skip, &references) {
+        (Ok(a), Ok(b)) => assert_eq!(a, b, "skip and references consumed different lengths"),
+        (Ok(_), Err(err)) => assert!(is_link_error(err), "only references failed: {:?}", err),
+        (Err(_), Ok(_)) => panic!("only skip failed: {:?}", skip),
+        (Err(_), Err(_)) => {}
+    }
+    match (&skip, &decode) {
+        (Ok(a), Ok(b)) => assert_eq!(a, b, "skip and decode consumed different lengths"),
+        (Ok(_), Err(err)) => assert!(is_data_model_error(err), "only decode failed: {:?}", err),
+        (Err(_), Ok(_)) => panic!("only skip failed: {:?}", skip),
+        (Err(_), Err(_)) => {}
+    }
+}
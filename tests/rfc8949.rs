@@ -0,0 +1,334 @@
+//! The examples of RFC 8949, Appendix A, and the not well-formed examples of Appendix F.
+use libipld_core::{
+    codec::{Codec, Decode},
+    ipld::Ipld,
+    raw_value::{RawValue, SkipOne},
+};
+use libipld_raw_cbor::{encoded_len::EncodedLen, value::Value, RawCborCodec};
+use std::io::Cursor;
+
+/// How decoding a well-formed example into `Ipld` and encoding it again behaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Support {
+    /// Encodes to the same bytes.
+    Supported,
+    /// Encodes to different bytes, e.g. a float with a different width, definite lengths or
+    /// null for undefined.
+    Lossy,
+    /// Can not be represented as `Ipld`, e.g. tags other than 42, simple values and maps with
+    /// non-string keys.
+    Rejected,
+}
+
+use Support::*;
+
+/// Appendix A: diagnostic notation, encoding and support.
+const APPENDIX_A: &[(&str, &str, Support)] = &[
+    ("0", "00", Supported),
+    ("1", "01", Supported),
+    ("10", "0a", Supported),
+    ("23", "17", Supported),
+    ("24", "1818", Supported),
+    ("25", "1819", Supported),
+    ("100", "1864", Supported),
+    ("1000", "1903e8", Supported),
+    ("1000000", "1a000f4240", Supported),
+    ("1000000000000", "1b000000e8d4a51000", Supported),
+    ("18446744073709551615", "1bffffffffffffffff", Supported),
+    ("18446744073709551616", "c249010000000000000000", Rejected),
+    ("-18446744073709551616", "3bffffffffffffffff", Supported),
+    ("-18446744073709551617", "c349010000000000000000", Rejected),
+    ("-1", "20", Supported),
+    ("-10", "29", Supported),
+    ("-100", "3863", Supported),
+    ("-1000", "3903e7", Supported),
+    ("0.0", "f90000", Lossy),
+    ("-0.0", "f98000", Lossy),
+    ("1.0", "f93c00", Lossy),
+    ("1.1", "fb3ff199999999999a", Supported),
+    ("1.5", "f93e00", Lossy),
+    ("65504.0", "f97bff", Lossy),
+    ("100000.0", "fa47c35000", Supported),
+    ("3.4028234663852886e+38", "fa7f7fffff", Supported),
+    ("1.0e+300", "fb7e37e43c8800759c", Supported),
+    ("5.960464477539063e-8", "f90001", Lossy),
+    ("0.00006103515625", "f90400", Lossy),
+    ("-4.0", "f9c400", Lossy),
+    ("-4.1", "fbc010666666666666", Supported),
+    ("Infinity", "f97c00", Supported),
+    ("NaN", "f97e00", Supported),
+    ("-Infinity", "f9fc00", Supported),
+    ("Infinity", "fa7f800000", Lossy),
+    ("NaN", "fa7fc00000", Lossy),
+    ("-Infinity", "faff800000", Lossy),
+    ("Infinity", "fb7ff0000000000000", Lossy),
+    ("NaN", "fb7ff8000000000000", Lossy),
+    ("-Infinity", "fbfff0000000000000", Lossy),
+    ("false", "f4", Supported),
+    ("true", "f5", Supported),
+    ("null", "f6", Supported),
+    ("undefined", "f7", Lossy),
+    ("simple(16)", "f0", Rejected),
+    ("simple(255)", "f8ff", Rejected),
+    (
+        "0(\"2013-03-21T20:04:00Z\")",
+        "c074323031332d30332d32315432303a30343a30305a",
+        Rejected,
+    ),
+    ("1(1363896240)", "c11a514b67b0", Rejected),
+    ("1(1363896240.5)", "c1fb41d452d9ec200000", Rejected),
+    ("23(h'01020304')", "d74401020304", Rejected),
+    ("24(h'6449455446')", "d818456449455446", Rejected),
+    (
+        "32(\"http://www.example.com\")",
+        "d82076687474703a2f2f7777772e6578616d706c652e636f6d",
+        Rejected,
+    ),
+    ("h''", "40", Supported),
+    ("h'01020304'", "4401020304", Supported),
+    ("\"\"", "60", Supported),
+    ("\"a\"", "6161", Supported),
+    ("\"IETF\"", "6449455446", Supported),
+    ("\"\\\"\\\\\"", "62225c", Supported),
+    ("\"\\u00fc\"", "62c3bc", Supported),
+    ("\"\\u6c34\"", "63e6b0b4", Supported),
+    ("\"\\ud800\\udd51\"", "64f0908591", Supported),
+    ("[]", "80", Supported),
+    ("[1, 2, 3]", "83010203", Supported),
+    ("[1, [2, 3], [4, 5]]", "8301820203820405", Supported),
+    (
+        "[1, 2, ..., 25]",
+        "98190102030405060708090a0b0c0d0e0f101112131415161718181819",
+        Supported,
+    ),
+    ("{}", "a0", Supported),
+    ("{1: 2, 3: 4}", "a201020304", Rejected),
+    ("{\"a\": 1, \"b\": [2, 3]}", "a26161016162820203", Supported),
+    ("[\"a\", {\"b\": \"c\"}]", "826161a161626163", Supported),
+    (
+        "{\"a\": \"A\", \"b\": \"B\", \"c\": \"C\", \"d\": \"D\", \"e\": \"E\"}",
+        "a56161614161626142616361436164614461656145",
+        Supported,
+    ),
+    ("(_ h'0102', h'030405')", "5f42010243030405ff", Lossy),
+    (
+        "(_ \"strea\", \"ming\")",
+        "7f657374726561646d696e67ff",
+        Lossy,
+    ),
+    ("[_ ]", "9fff", Lossy),
+    ("[_ 1, [2, 3], [_ 4, 5]]", "9f018202039f0405ffff", Lossy),
+    ("[_ 1, [2, 3], [4, 5]]", "9f01820203820405ff", Lossy),
+    ("[1, [2, 3], [_ 4, 5]]", "83018202039f0405ff", Lossy),
+    ("[1, [_ 2, 3], [4, 5]]", "83019f0203ff820405", Lossy),
+    (
+        "[_ 1, 2, ..., 25]",
+        "9f0102030405060708090a0b0c0d0e0f101112131415161718181819ff",
+        Lossy,
+    ),
+    (
+        "{_ \"a\": 1, \"b\": [_ 2, 3]}",
+        "bf61610161629f0203ffff",
+        Lossy,
+    ),
+    ("[\"a\", {_ \"b\": \"c\"}]", "826161bf61626163ff", Lossy),
+    (
+        "{_ \"Fun\": true, \"Amt\": -2}",
+        "bf6346756ef563416d7421ff",
+        Lossy,
+    ),
+];
+
+/// Appendix F.1: examples that are not well-formed.
+const APPENDIX_F: &[&str] = &[
+    // end of input in a head
+    "18",
+    "19",
+    "1a",
+    "1b",
+    "1901",
+    "1a0102",
+    "1b01020304050607",
+    "38",
+    "58",
+    "78",
+    "98",
+    "9a01ff00",
+    "b8",
+    "d8",
+    "f8",
+    "f900",
+    "fa0000",
+    "fb000000",
+    // definite length strings with short data
+    "41",
+    "61",
+    "5affffffff00",
+    "5bffffffffffffffff010203",
+    "7affffffff00",
+    "7b7fffffffffffffff010203",
+    // definite length maps and arrays not closed with enough items
+    "81",
+    "818181818181818181",
+    "8200",
+    "a1",
+    "a20102",
+    "a100",
+    "a2000000",
+    // tag number not followed by tag content
+    "c0",
+    // indefinite length strings not closed by a break
+    "5f4100",
+    "7f6100",
+    // indefinite length maps and arrays not closed by a break
+    "9f",
+    "9f0102",
+    "bf",
+    "bf01020102",
+    "819f",
+    "9f8000",
+    "9f9f9f9f9fffffffff",
+    "9f819f819f9fffffff",
+    // reserved additional information values
+    "1c",
+    "1d",
+    "1e",
+    "3c",
+    "3d",
+    "3e",
+    "5c",
+    "5d",
+    "5e",
+    "7c",
+    "7d",
+    "7e",
+    "9c",
+    "9d",
+    "9e",
+    "bc",
+    "bd",
+    "be",
+    "dc",
+    "dd",
+    "de",
+    "fc",
+    "fd",
+    "fe",
+    // reserved two byte encodings of simple values
+    "f800",
+    "f801",
+    "f818",
+    "f81f",
+    // indefinite length string chunks not of the correct type
+    "5f00ff",
+    "5f21ff",
+    "5f6100ff",
+    "5f80ff",
+    "5fa0ff",
+    "5fc000ff",
+    "5fe0ff",
+    "7f4100ff",
+    // indefinite length string chunks not of definite length
+    "5f5f4100ffff",
+    "7f7f6100ffff",
+    // break on its own outside of an indefinite length item
+    "ff",
+    // break in a definite length array or map or a tag
+    "81ff",
+    "8200ff",
+    "a1ff",
+    "a1ff00",
+    "a100ff",
+    "a20000ff",
+    "9f81ff",
+    "9f829f819f9fffffffff",
+    // break in the value position of an indefinite length map
+    "bf00ff",
+    "bf000000ff",
+    // major types 0, 1 and 6 with additional information 31
+    "1f",
+    "3f",
+    "df",
+];
+
+#[test]
+fn appendix_a_skip() {
+    for (diag, hex, _) in APPENDIX_A {
+        let bytes = hex::decode(hex).unwrap();
+        let mut r = Cursor::new(&bytes);
+        RawCborCodec
+            .skip(&mut r)
+            .unwrap_or_else(|err| panic!("skipping {} failed: {}", diag, err));
+        assert_eq!(r.position(), bytes.len() as u64, "skipping {}", diag);
+    }
+}
+
+#[test]
+fn appendix_a_raw_value() {
+    for (diag, hex, _) in APPENDIX_A {
+        let bytes = hex::decode(hex).unwrap();
+        let raw: RawValue<RawCborCodec> = RawCborCodec
+            .decode(&bytes)
+            .unwrap_or_else(|err| panic!("decoding {} failed: {}", diag, err));
+        assert_eq!(RawCborCodec.encode(&raw).unwrap(), bytes, "{}", diag);
+    }
+}
+
+#[test]
+fn appendix_a_value() {
+    for (diag, hex, _) in APPENDIX_A {
+        let bytes = hex::decode(hex).unwrap();
+        let value: Value = RawCborCodec
+            .decode(&bytes)
+            .unwrap_or_else(|err| panic!("decoding {} failed: {}", diag, err));
+        assert_eq!(value.encoded_len(), bytes.len(), "{}", diag);
+        assert_eq!(RawCborCodec.encode(&value).unwrap(), bytes, "{}", diag);
+    }
+}
+
+#[test]
+fn appendix_a_ipld() {
+    for (diag, hex, support) in APPENDIX_A {
+        let bytes = hex::decode(hex).unwrap();
+        let ipld = match RawCborCodec.decode::<Ipld>(&bytes) {
+            Ok(ipld) => ipld,
+            Err(_) if *support == Rejected => continue,
+            Err(err) => panic!("decoding {} failed: {}", diag, err),
+        };
+        assert_ne!(*support, Rejected, "decoding {} succeeded", diag);
+        let encoded = RawCborCodec.encode(&ipld).unwrap();
+        assert_eq!(encoded == bytes, *support == Supported, "{}", diag);
+        // whatever is lost, encoding again does not lose more
+        let ipld: Ipld = RawCborCodec.decode(&encoded).unwrap();
+        assert_eq!(RawCborCodec.encode(&ipld).unwrap(), encoded, "{}", diag);
+    }
+}
+
+#[test]
+fn appendix_f() {
+    for hex in APPENDIX_F {
+        let bytes = hex::decode(hex).unwrap();
+        assert!(
+            RawCborCodec.skip(&mut Cursor::new(&bytes)).is_err(),
+            "skipping {} succeeded",
+            hex
+        );
+        assert!(
+            Ipld::decode(RawCborCodec, &mut Cursor::new(&bytes)).is_err(),
+            "decoding {} succeeded",
+            hex
+        );
+        assert!(
+            RawCborCodec
+                .decode::<RawValue<RawCborCodec>>(&bytes)
+                .is_err(),
+            "decoding {} as a raw value succeeded",
+            hex
+        );
+        assert!(
+            RawCborCodec.decode::<Value>(&bytes).is_err(),
+            "decoding {} as a value succeeded",
+            hex
+        );
+    }
+}